@@ -0,0 +1,452 @@
+//! Keyframed values changing over time, and a text format to describe
+//! them in.
+//!
+//! An animation file has a keyframe on every line:
+//!
+//! ```text
+//! # track      time  interpolation  value  [control control]
+//! camera_from  0.0   catmull-rom    0,1,1.5
+//! fuzz         0.0   bezier         0.05   0.05 0.6
+//! loop camera_from
+//! ```
+//!
+//! Values with more than one component are separated by commas, the
+//! interpolation is `step', `linear', `catmull-rom' or `bezier' with
+//! the two inner control points.  `loop' makes a track repeat.  Blank
+//! lines and everything after `#' are ignored.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+    path::Path,
+};
+
+use crate::{color::Color, p3::Point3, v3::Vec3};
+
+/// Values that can be blended between keyframes.
+pub trait Animatable: Copy {
+    fn add(self, other: Self) -> Self;
+    fn scale(self, factor: f64) -> Self;
+    /// The value of an empty track, adding it changes nothing.
+    fn zero() -> Self;
+    /// Value from the components written in an animation file.
+    fn from_components(components: &[f64]) -> Option<Self>;
+
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self.scale(1.0 - t).add(other.scale(t))
+    }
+}
+
+impl Animatable for f64 {
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        self * factor
+    }
+
+    fn zero() -> Self {
+        0.0
+    }
+
+    fn from_components(components: &[f64]) -> Option<Self> {
+        match components {
+            [value] => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl Animatable for Vec3 {
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Vec3::scale(self, factor)
+    }
+
+    fn zero() -> Self {
+        Vec3::zero()
+    }
+
+    fn from_components(components: &[f64]) -> Option<Self> {
+        match components {
+            [x, y, z] => Some(Vec3::new(*x, *y, *z)),
+            _ => None,
+        }
+    }
+}
+
+impl Animatable for Point3 {
+    fn add(self, other: Self) -> Self {
+        self + other.into()
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Point3::zero() + Vec3::from(self).scale(factor)
+    }
+
+    fn zero() -> Self {
+        Point3::zero()
+    }
+
+    fn from_components(components: &[f64]) -> Option<Self> {
+        match components {
+            [x, y, z] => Some(Point3::new(*x, *y, *z)),
+            _ => None,
+        }
+    }
+}
+
+impl Animatable for Color {
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Color::scale(self, factor)
+    }
+
+    fn zero() -> Self {
+        Color::rgb(0.0, 0.0, 0.0)
+    }
+
+    fn from_components(components: &[f64]) -> Option<Self> {
+        match components {
+            [r, g, b] => Some(Color::rgb(*r, *g, *b)),
+            _ => None,
+        }
+    }
+}
+
+/// How to get from a keyframe to the next one.
+#[derive(Clone, Copy, Debug)]
+pub enum Interpolation<T> {
+    /// Hold the value until the next keyframe.
+    Step,
+    Linear,
+    /// Smooth curve through the neighbouring keyframes.
+    CatmullRom,
+    /// Cubic Bézier with the two inner control points given.
+    Bezier(T, T),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Keyframe<T> {
+    time: f64,
+    value: T,
+    interpolation: Interpolation<T>,
+}
+
+/// Value of a property changing over time.  Before the first and
+/// after the last keyframe the track holds its value, unless it
+/// loops.  A track without keyframes is zero.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    // sorted by time
+    keyframes: Vec<Keyframe<T>>,
+    looping: bool,
+}
+
+impl<T: Animatable> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            looping: false,
+        }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self::new().key(0.0, value, Interpolation::Step)
+    }
+
+    /// Add a keyframe.  `interpolation` is used for the segment
+    /// starting at this keyframe.
+    pub fn key(mut self, time: f64, value: T, interpolation: Interpolation<T>) -> Self {
+        let index = self.keyframes.partition_point(|k| k.time <= time);
+        self.keyframes.insert(
+            index,
+            Keyframe {
+                time,
+                value,
+                interpolation,
+            },
+        );
+        self
+    }
+
+    /// Repeat the keyframes over and over.  The last keyframe should
+    /// have the value of the first, curves go on smoothly through it.
+    pub fn looping(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return T::zero(),
+        };
+        let period = last.time - first.time;
+        let time = if self.looping && period > 0.0 {
+            first.time + (time - first.time).rem_euclid(period)
+        } else {
+            time
+        };
+
+        let len = self.keyframes.len();
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return first.value;
+        }
+        if next == len {
+            return last.value;
+        }
+
+        let from = &self.keyframes[next - 1];
+        let to = &self.keyframes[next];
+        let t = (time - from.time) / (to.time - from.time);
+        match from.interpolation {
+            Interpolation::Step => from.value,
+            Interpolation::Linear => from.value.lerp(to.value, t),
+            Interpolation::CatmullRom => {
+                // the last keyframe of a loop is the first one again
+                let before = match next {
+                    1 if self.looping => len.saturating_sub(2),
+                    _ => next.saturating_sub(2),
+                };
+                let after = match next + 1 {
+                    after if after == len && self.looping => 1.min(len - 1),
+                    after => after.min(len - 1),
+                };
+                catmull_rom(
+                    self.keyframes[before].value,
+                    from.value,
+                    to.value,
+                    self.keyframes[after].value,
+                    t,
+                )
+            }
+            Interpolation::Bezier(c1, c2) => bezier(from.value, c1, c2, to.value, t),
+        }
+    }
+}
+
+/// Keyframes of the tracks in an animation file, by name.  The values
+/// are only numbers until a track is asked for with its type.
+pub struct AnimationFile {
+    tracks: HashMap<String, FileTrack>,
+}
+
+#[derive(Default)]
+struct FileTrack {
+    /// Line number, time, interpolation name and values, with the
+    /// value first.
+    keyframes: Vec<(usize, f64, String, Vec<Vec<f64>>)>,
+    looping: bool,
+}
+
+impl AnimationFile {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut tracks: HashMap<String, FileTrack> = HashMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let number = index + 1;
+            let fields: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .collect();
+            match fields.as_slice() {
+                [] => {}
+                ["loop", name] => tracks.entry(name.to_string()).or_default().looping = true,
+                [name, time, interpolation, values @ ..] if !values.is_empty() => {
+                    let time = time
+                        .parse()
+                        .map_err(|_| invalid(number, &format!("bad time {}", time)))?;
+                    let values = values
+                        .iter()
+                        .map(|value| {
+                            value
+                                .split(',')
+                                .map(|component| component.parse())
+                                .collect::<Result<Vec<f64>, _>>()
+                                .map_err(|_| invalid(number, &format!("bad value {}", value)))
+                        })
+                        .collect::<io::Result<_>>()?;
+                    tracks.entry(name.to_string()).or_default().keyframes.push((
+                        number,
+                        time,
+                        interpolation.to_string(),
+                        values,
+                    ));
+                }
+                _ => return Err(invalid(number, "expected a keyframe or a loop")),
+            }
+        }
+        Ok(Self { tracks })
+    }
+
+    /// Names of the tracks in the file.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tracks.keys().map(String::as_str)
+    }
+
+    /// The track `name' with values of type `T'.
+    pub fn track<T: Animatable>(&self, name: &str) -> io::Result<Track<T>> {
+        let file_track = self
+            .tracks
+            .get(name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no track {}", name)))?;
+        let mut track = Track::new();
+        for (number, time, interpolation, values) in &file_track.keyframes {
+            let value = |components: &Vec<f64>| {
+                T::from_components(components).ok_or_else(|| {
+                    invalid(
+                        *number,
+                        &format!("{} components don't fit {}", components.len(), name),
+                    )
+                })
+            };
+            let interpolation = match (interpolation.as_str(), values.as_slice()) {
+                ("step", [_]) => Interpolation::Step,
+                ("linear", [_]) => Interpolation::Linear,
+                ("catmull-rom", [_]) => Interpolation::CatmullRom,
+                ("bezier", [_, c1, c2]) => Interpolation::Bezier(value(c1)?, value(c2)?),
+                (interpolation, values) => {
+                    return Err(invalid(
+                        *number,
+                        &format!("{} with {} values", interpolation, values.len()),
+                    ))
+                }
+            };
+            track = track.key(*time, value(&values[0])?, interpolation);
+        }
+        if file_track.looping {
+            track = track.looping();
+        }
+        Ok(track)
+    }
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+fn catmull_rom<T: Animatable>(p0: T, p1: T, p2: T, p3: T, t: f64) -> T {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p0.scale(-0.5 * t3 + t2 - 0.5 * t)
+        .add(p1.scale(1.5 * t3 - 2.5 * t2 + 1.0))
+        .add(p2.scale(-1.5 * t3 + 2.0 * t2 + 0.5 * t))
+        .add(p3.scale(0.5 * t3 - 0.5 * t2))
+}
+
+fn bezier<T: Animatable>(p0: T, p1: T, p2: T, p3: T, t: f64) -> T {
+    let s = 1.0 - t;
+    p0.scale(s * s * s)
+        .add(p1.scale(3.0 * s * s * t))
+        .add(p2.scale(3.0 * s * t * t))
+        .add(p3.scale(t * t * t))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close_enough(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-10
+    }
+
+    #[test]
+    pub fn holds_value_outside_of_keyframes() {
+        let track = Track::new()
+            .key(1.0, 2.0, Interpolation::Linear)
+            .key(2.0, 4.0, Interpolation::Linear);
+        assert!(close_enough(track.at(0.0), 2.0));
+        assert!(close_enough(track.at(3.0), 4.0));
+        assert!(close_enough(track.at(1.5), 3.0));
+    }
+
+    #[test]
+    pub fn curves_pass_through_keyframes() {
+        let keys = [(0.0, 1.0), (1.0, 3.0), (2.0, -1.0), (3.0, 0.5)];
+        let catmull_rom = keys.iter().fold(Track::new(), |track, (time, value)| {
+            track.key(*time, *value, Interpolation::CatmullRom)
+        });
+        let bezier = keys.iter().fold(Track::new(), |track, (time, value)| {
+            track.key(*time, *value, Interpolation::Bezier(10.0, -10.0))
+        });
+        for (time, value) in keys {
+            assert!(close_enough(catmull_rom.at(time), value));
+            assert!(close_enough(bezier.at(time), value));
+        }
+    }
+
+    #[test]
+    pub fn empty_tracks_are_zero() {
+        assert!(close_enough(Track::<f64>::new().at(1.0), 0.0));
+    }
+
+    #[test]
+    pub fn loops_have_no_kinks() {
+        let keys = 6;
+        let track = (0..=keys)
+            .fold(Track::new(), |track, key| {
+                let time = key as f64 / keys as f64;
+                let value = (2.0 * std::f64::consts::PI * time).sin();
+                track.key(time, value, Interpolation::CatmullRom)
+            })
+            .looping();
+        let slope = |time: f64| (track.at(time + 1e-6) - track.at(time - 1e-6)) / 2e-6;
+        // the same on both sides of the first keyframe
+        assert!((slope(1e-6) - slope(1.0 - 1e-6)).abs() < 1e-3);
+        assert!(close_enough(track.at(1.25), track.at(0.25)));
+    }
+
+    #[test]
+    pub fn reads_tracks_from_files() {
+        let text = "
+            # the camera goes around
+            from  0.0  catmull-rom  0,1,2
+            from  1.0  linear       2,1,0  # and stops
+            fuzz  0.5  bezier       0.1   0.2 0.3
+            fuzz  1.5  step         0.4
+            loop fuzz
+        ";
+        let file = AnimationFile::read(text.as_bytes()).unwrap();
+        let mut names: Vec<&str> = file.names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["from", "fuzz"]);
+
+        let from = file.track::<Point3>("from").unwrap();
+        assert!(close_enough(from.at(1.0).xyz().0, 2.0));
+        let fuzz = file.track::<f64>("fuzz").unwrap();
+        assert!(close_enough(fuzz.at(0.5), 0.1));
+        assert!(close_enough(fuzz.at(2.5), 0.1));
+
+        assert!(file.track::<f64>("from").is_err());
+        assert!(file.track::<f64>("nothing").is_err());
+        assert!(AnimationFile::read("fuzz 0.0 bezier 0.1".as_bytes())
+            .unwrap()
+            .track::<f64>("fuzz")
+            .is_err());
+        assert!(AnimationFile::read("fuzz zero step 0.1".as_bytes()).is_err());
+    }
+}
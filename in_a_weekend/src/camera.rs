@@ -1,16 +1,27 @@
-use crate::{p3::Point3, v3::Vec3};
+use crate::{p3::Point3, ray::Ray, v3::Vec3};
 
 pub const ASPECT_RATIO: f64 = 1.0;
 
+/// Pinhole camera looking at a point, it can move while its shutter
+/// is open.
 pub struct Camera {
+    start: View,
+    end: View,
+    shutter_open: f64,
+    shutter_close: f64,
+}
+
+/// Viewport of the camera at a single point in time.
+#[derive(Clone, Copy)]
+struct View {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
     vertical: Vec3,
 }
 
-impl Camera {
-    pub fn new(look_from: Point3, look_at: Point3, view_up: Vec3) -> Self {
+impl View {
+    fn new(look_from: Point3, look_at: Point3, view_up: Vec3) -> Self {
         let viewport_height = 2.0;
         let viewport_width = viewport_height * ASPECT_RATIO;
 
@@ -31,11 +42,61 @@ impl Camera {
         }
     }
 
-    pub fn dir(&self, u: f64, v: f64) -> Vec3 {
-        (self.lower_left_corner - self.origin) + self.horizontal.scale(u) + self.vertical.scale(v)
+    fn lerp(self, other: Self, t: f64) -> Self {
+        Self {
+            origin: self.origin + (other.origin - self.origin).scale(t),
+            lower_left_corner: self.lower_left_corner
+                + (other.lower_left_corner - self.lower_left_corner).scale(t),
+            horizontal: self.horizontal + (other.horizontal - self.horizontal).scale(t),
+            vertical: self.vertical + (other.vertical - self.vertical).scale(t),
+        }
+    }
+}
+
+impl Camera {
+    pub fn new(look_from: Point3, look_at: Point3, view_up: Vec3) -> Self {
+        let view = View::new(look_from, look_at, view_up);
+        Self {
+            start: view,
+            end: view,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+
+    /// Rays are sent out at random times between `open` and `close`.
+    pub fn with_shutter(self, open: f64, close: f64) -> Self {
+        Self {
+            shutter_open: open,
+            shutter_close: close,
+            ..self
+        }
+    }
+
+    /// Where the camera is when the shutter closes.  The viewport is
+    /// interpolated linearly while the shutter is open.
+    pub fn moving_to(self, look_from: Point3, look_at: Point3, view_up: Vec3) -> Self {
+        Self {
+            end: View::new(look_from, look_at, view_up),
+            ..self
+        }
+    }
+
+    /// Times the shutter opens and closes.
+    pub fn shutter(&self) -> (f64, f64) {
+        (self.shutter_open, self.shutter_close)
     }
 
-    pub fn origin(&self) -> Point3 {
-        self.origin
+    /// Ray through `u', `v' of the viewport, `shutter' in `[0, 1)' of
+    /// the way through the time the shutter is open.
+    pub fn ray(&self, u: f64, v: f64, shutter: f64) -> Ray {
+        let shutter_len = self.shutter_close - self.shutter_open;
+        let t = if shutter_len > 0.0 { shutter } else { 0.0 };
+        let time = self.shutter_open + t * shutter_len;
+        let view = self.start.lerp(self.end, t);
+        let dir = (view.lower_left_corner - view.origin)
+            + view.horizontal.scale(u)
+            + view.vertical.scale(v);
+        Ray::new(view.origin, dir, time)
     }
 }
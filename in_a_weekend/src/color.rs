@@ -31,6 +31,11 @@ impl Color {
         self.0.z()
     }
 
+    /// Relative luminance of linear sRGB.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
     pub fn sampled(self, sample_per_pixel: usize) -> Self {
         let (e1, e2, e3) = self.0.xyz();
         let e1 = f64::clamp(e1 / sample_per_pixel as f64, 0.0, 1.0);
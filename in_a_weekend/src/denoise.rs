@@ -0,0 +1,232 @@
+//! Joint bilateral filter for the beauty, guided by the albedo, normal
+//! and depth outputs of the film.
+//!
+//! The light is divided by the albedo before filtering and multiplied
+//! back afterwards, so textures stay sharp.  Neighbours count less the
+//! more their guides differ, which keeps edges between objects.  The
+//! noisy light itself only guides after a small blur, with a loose
+//! tolerance, to keep shadow edges without keeping the noise.
+
+use crate::{
+    color::Color,
+    film::{Aov, Film},
+};
+
+/// The outputs the filter needs rendered.
+pub const GUIDES: [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    /// Pixels looked at in every direction.
+    pub radius: usize,
+    /// Standard deviation of the spatial falloff in pixels.
+    pub sigma_spatial: f64,
+    pub sigma_albedo: f64,
+    /// For normals as colors.
+    pub sigma_normal: f64,
+    /// Relative to the depth of the filtered pixel.
+    pub sigma_depth: f64,
+    /// Relative to the luminance of the filtered pixel.
+    pub sigma_light: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            radius: 6,
+            sigma_spatial: 3.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.1,
+            sigma_depth: 0.05,
+            sigma_light: 1.0,
+        }
+    }
+}
+
+impl Denoiser {
+    /// Filter over `radius' pixels around each one.  The spatial
+    /// falloff doesn't get narrower than half a pixel, a radius of 0
+    /// leaves the image as it is.
+    pub fn with_radius(radius: usize) -> Self {
+        Self {
+            radius,
+            sigma_spatial: (radius as f64 / 2.0).max(0.5),
+            ..Self::default()
+        }
+    }
+
+    /// Filtered light reaching every pixel of `film'.  Guides that
+    /// weren't rendered are left out.
+    pub fn denoise(&self, film: &Film) -> Vec<Color> {
+        let width = film.size().width;
+        let height = film.size().height;
+        let pixels = width * height;
+        let means = |buffer: &[Color]| {
+            (0..pixels)
+                .map(|index| film.mean(buffer, index))
+                .collect::<Vec<_>>()
+        };
+        let guide = |aov| film.layer(aov).map(means);
+        let albedo = guide(Aov::Albedo);
+        let normal = guide(Aov::Normal);
+        let depth = guide(Aov::Depth);
+
+        // misses and black surfaces are left as they are
+        let divisor = |index: usize| {
+            let safe = |a: f64| if a > 0.01 { a } else { 1.0 };
+            albedo.as_ref().map_or(Color::rgb(1.0, 1.0, 1.0), |albedo| {
+                let a = albedo[index];
+                Color::rgb(safe(a.r()), safe(a.g()), safe(a.b()))
+            })
+        };
+        let light: Vec<Color> = means(film.beauty())
+            .into_iter()
+            .enumerate()
+            .map(|(index, mean)| divide(mean, divisor(index)))
+            .collect();
+        let blurred = box_blur(&light, width, height);
+
+        let gaussian =
+            |difference: f64, sigma: f64| (-difference * difference / (2.0 * sigma * sigma)).exp();
+        let distance = |a: Color, b: Color| {
+            let d = Color::rgb(a.r() - b.r(), a.g() - b.g(), a.b() - b.b());
+            (d.r() * d.r() + d.g() * d.g() + d.b() * d.b()).sqrt()
+        };
+
+        let radius = self.radius as isize;
+        let mut filtered = Vec::with_capacity(light.len());
+        for y in 0..height {
+            for x in 0..width {
+                let p = y * width + x;
+                let mut sum = Color::rgb(0.0, 0.0, 0.0);
+                let mut weights = 0.0;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let (qx, qy) = (x as isize + dx, y as isize + dy);
+                        if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                            continue;
+                        }
+                        let q = qy as usize * width + qx as usize;
+                        let pixels = ((dx * dx + dy * dy) as f64).sqrt();
+                        let mut weight = gaussian(pixels, self.sigma_spatial);
+                        if let Some(albedo) = &albedo {
+                            weight *= gaussian(distance(albedo[p], albedo[q]), self.sigma_albedo);
+                        }
+                        if let Some(normal) = &normal {
+                            weight *= gaussian(distance(normal[p], normal[q]), self.sigma_normal);
+                        }
+                        if let Some(depth) = &depth {
+                            let relative = (depth[p].r() - depth[q].r()) / depth[p].r().max(1e-3);
+                            weight *= gaussian(relative, self.sigma_depth);
+                        }
+                        let luminance = blurred[p].luminance();
+                        let relative = (luminance - blurred[q].luminance()) / (luminance + 1e-2);
+                        weight *= gaussian(relative, self.sigma_light);
+
+                        sum += light[q].scale(weight);
+                        weights += weight;
+                    }
+                }
+                // the pixel itself always has weight 1
+                filtered.push(sum.scale(1.0 / weights) * divisor(p));
+            }
+        }
+        filtered
+    }
+}
+
+fn divide(a: Color, b: Color) -> Color {
+    Color::rgb(a.r() / b.r(), a.g() / b.g(), a.b() / b.b())
+}
+
+/// 3 by 3 box blur, the edges only average what's inside.
+fn box_blur(pixels: &[Color], width: usize, height: usize) -> Vec<Color> {
+    let mut blurred = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = Color::rgb(0.0, 0.0, 0.0);
+            let mut count = 0;
+            for qy in y.saturating_sub(1)..(y + 2).min(height) {
+                for qx in x.saturating_sub(1)..(x + 2).min(width) {
+                    sum += pixels[qy * width + qx];
+                    count += 1;
+                }
+            }
+            blurred.push(sum.scale(1.0 / count as f64));
+        }
+    }
+    blurred
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Rect;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    pub fn smooths_noise_but_keeps_albedo_edges() {
+        let size = Rect {
+            width: 32,
+            height: 32,
+        };
+        let mut film = Film::new(size, &GUIDES);
+        let mut rng = StdRng::seed_from_u64(3);
+        let flat = |albedo: f64| Color::rgb(albedo, albedo, albedo);
+        for y in 0..32 {
+            for x in 0..32 {
+                // left half dark, right half bright, lit evenly
+                let albedo = if x < 16 { 0.2 } else { 0.8 };
+                let noise = rng.gen_range(0.5..1.5);
+                let normal = Color::from_normal(crate::v3::Vec3::new(0.0, 0.0, 1.0));
+                film.add(
+                    x,
+                    y,
+                    1.0,
+                    flat(albedo * noise),
+                    &[flat(albedo), normal, flat(2.0)],
+                );
+            }
+        }
+
+        let denoised = Denoiser::default().denoise(&film);
+        let error = |x: usize, y: usize, expected: f64| (denoised[y * 32 + x].r() - expected).abs();
+        // away from the image borders, on both sides of the edge
+        for y in 8..24 {
+            assert!(error(8, y, 0.2) < 0.03);
+            assert!(error(15, y, 0.2) < 0.03);
+            assert!(error(16, y, 0.8) < 0.12);
+            assert!(error(24, y, 0.8) < 0.12);
+        }
+    }
+
+    #[test]
+    pub fn radius_0_keeps_the_image() {
+        let size = Rect {
+            width: 4,
+            height: 4,
+        };
+        let mut film = Film::new(size, &GUIDES);
+        let mut rng = StdRng::seed_from_u64(5);
+        let normal = Color::from_normal(crate::v3::Vec3::new(0.0, 0.0, 1.0));
+        for y in 0..4 {
+            for x in 0..4 {
+                let albedo = Color::rgb(0.5, 0.5, 0.5);
+                let light = rng.gen_range(0.0..1.0);
+                film.add(
+                    x,
+                    y,
+                    1.0,
+                    Color::rgb(light, light, light),
+                    &[albedo, normal, Color::rgb(2.0, 2.0, 2.0)],
+                );
+            }
+        }
+
+        let denoised = Denoiser::with_radius(0).denoise(&film);
+        for (index, pixel) in denoised.iter().enumerate() {
+            let mean = film.mean(film.beauty(), index);
+            assert!((pixel.r() - mean.r()).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Pixels of a frame being rendered: the beauty image and the extra
+//! outputs next to it.
+
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use crate::{color::Color, ppm::Ppm, Rect};
+
+/// Arbitrary output variables, images of what the camera rays find
+/// besides the light.  Most come from the first hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    /// Average attenuation of the first scatter.
+    Albedo,
+    /// World space normal facing the camera, as a color.
+    Normal,
+    /// Distance along the camera ray.
+    Depth,
+    /// World space point.
+    Position,
+    /// False color for every material.  Materials are told apart by
+    /// identity, the colors change between frames.
+    MaterialId,
+    /// False color for every object of the scene.
+    ObjectId,
+    /// Light seen directly or after one bounce.  Adds up to the beauty
+    /// with `Indirect'.  Integrators other than the path tracer trace
+    /// an extra path for the split.
+    Direct,
+    /// Light after more than one bounce.
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    /// Turn the mean `value' into something a PPM can show.
+    fn display(&self, value: Color) -> Color {
+        // squash unbounded values into `[0, 1]'
+        let squash = |x: f64| 0.5 + 0.5 * x / (1.0 + x.abs());
+        match self {
+            Aov::Albedo | Aov::Normal | Aov::MaterialId | Aov::ObjectId => value,
+            // near is bright, misses are black
+            Aov::Depth => {
+                let depth = value.r();
+                let shade = if depth > 0.0 {
+                    1.0 / (1.0 + depth)
+                } else {
+                    0.0
+                };
+                Color::rgb(shade, shade, shade)
+            }
+            Aov::Position => Color::rgb(squash(value.r()), squash(value.g()), squash(value.b())),
+            Aov::Direct | Aov::Indirect => tone_map(value.sampled(1)),
+        }
+    }
+}
+
+/// False color for an id.  Consecutive ids get hues far apart.
+pub fn id_color(id: u64) -> Color {
+    // Fibonacci hashing
+    let hue = (id.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 11) as f64 / (1u64 << 53) as f64;
+    let channel = |offset: f64| {
+        let distance = ((hue + offset).fract() * 6.0 - 3.0).abs();
+        0.2 + 0.7 * (distance - 1.0).clamp(0.0, 1.0)
+    };
+    Color::rgb(channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+}
+
+fn tone_map(color: Color) -> Color {
+    color.scale(16.0).reinhard().gamma_corrected()
+}
+
+/// How many samples pixels get.
+#[derive(Clone, Copy, Debug)]
+pub enum SampleCount {
+    Fixed(usize),
+    /// Sampling a pixel stops once the relative standard error of its
+    /// luminance is below `max_error', but not before `min' samples.
+    Adaptive {
+        min: usize,
+        max: usize,
+        max_error: f64,
+    },
+}
+
+impl SampleCount {
+    pub fn max(&self) -> usize {
+        match self {
+            SampleCount::Fixed(samples) => *samples,
+            SampleCount::Adaptive { max, .. } => *max,
+        }
+    }
+
+    /// Whether the pixel at `index' of `film' has enough samples.
+    pub fn is_done(&self, film: &Film, index: usize) -> bool {
+        let samples = film.samples[index];
+        match self {
+            SampleCount::Fixed(fixed) => samples >= *fixed,
+            SampleCount::Adaptive {
+                min,
+                max,
+                max_error,
+            } => samples >= *max || (samples >= *min && film.relative_error(index) < *max_error),
+        }
+    }
+}
+
+/// Weighted sums of the samples taken for every pixel.
+pub struct Film {
+    size: Rect,
+    aovs: Vec<Aov>,
+    beauty: Vec<Color>,
+    /// A buffer for every one of `aovs'.
+    layers: Vec<Vec<Color>>,
+    /// Sums of the filter weights of the samples.
+    weights: Vec<f64>,
+    samples: Vec<usize>,
+    /// Running mean of the luminance of the samples.
+    mean: Vec<f64>,
+    /// Running sum of squared differences from the mean.
+    m2: Vec<f64>,
+    /// Samples that weren't finite and were left out.
+    dropped: usize,
+}
+
+impl Film {
+    pub fn new(size: Rect, aovs: &[Aov]) -> Self {
+        let pixels = size.width * size.height;
+        let black = Color::rgb(0.0, 0.0, 0.0);
+        Self {
+            size,
+            aovs: aovs.to_vec(),
+            beauty: vec![black; pixels],
+            layers: vec![vec![black; pixels]; aovs.len()],
+            weights: vec![0.0; pixels],
+            samples: vec![0; pixels],
+            mean: vec![0.0; pixels],
+            m2: vec![0.0; pixels],
+            dropped: 0,
+        }
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    /// Add a sample with filter `weight' to the pixel at `x', `y'
+    /// counted from the bottom left.  `aovs' are in the order the film
+    /// was made with.  Samples with a beauty that isn't finite are
+    /// dropped and counted.
+    pub fn add(&mut self, x: usize, y: usize, weight: f64, beauty: Color, aovs: &[Color]) {
+        if !beauty.luminance().is_finite() {
+            self.dropped += 1;
+            return;
+        }
+        let index = self.index(x, y);
+        self.beauty[index] += beauty.scale(weight);
+        for (layer, value) in self.layers.iter_mut().zip(aovs) {
+            layer[index] += value.scale(weight);
+        }
+        self.weights[index] += weight;
+
+        // Welford's algorithm
+        self.samples[index] += 1;
+        let luminance = beauty.luminance();
+        let delta = luminance - self.mean[index];
+        self.mean[index] += delta / self.samples[index] as f64;
+        self.m2[index] += delta * (luminance - self.mean[index]);
+    }
+
+    /// Index of the pixel at `x', `y' in the buffers.
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        y * self.size.width + x
+    }
+
+    /// Number of samples dropped because they weren't finite.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Number of samples taken for every pixel.
+    pub fn samples(&self) -> &[usize] {
+        &self.samples
+    }
+
+    /// Standard error of the mean luminance of the pixel at `index',
+    /// relative to the mean.  Infinite until there are two samples.
+    pub fn relative_error(&self, index: usize) -> f64 {
+        let samples = self.samples[index];
+        if samples < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2[index] / (samples - 1) as f64;
+        // dark pixels don't need to be exact
+        (variance / samples as f64).sqrt() / self.mean[index].max(1e-3)
+    }
+
+    /// Weighted mean of the sums in `buffer' at the pixel at `index'.
+    pub fn mean(&self, buffer: &[Color], index: usize) -> Color {
+        let weight = self.weights[index];
+        if weight.abs() < 1e-9 {
+            return Color::rgb(0.0, 0.0, 0.0);
+        }
+        buffer[index].scale(1.0 / weight)
+    }
+
+    pub fn size(&self) -> &Rect {
+        &self.size
+    }
+
+    /// Weighted sums of the beauty samples.
+    pub fn beauty(&self) -> &[Color] {
+        &self.beauty
+    }
+
+    /// Weighted sums of the samples of `aov', if it is rendered.
+    pub fn layer(&self, aov: Aov) -> Option<&[Color]> {
+        let index = self.aovs.iter().position(|&rendered| rendered == aov)?;
+        Some(&self.layers[index])
+    }
+
+    /// Write the beauty to `{name}.ppm' and every output variable to
+    /// `{name}_{aov}.ppm'.  The beauty comes last, once it is there
+    /// the rest is too.
+    pub fn write(&self, name: &str) -> std::io::Result<()> {
+        for (aov, layer) in self.aovs.iter().zip(&self.layers) {
+            self.write_pixels(&format!("{}_{}.ppm", name, aov.name()), |index| {
+                aov.display(self.mean(layer, index))
+            })?;
+        }
+        self.write_pixels(&format!("{}.ppm", name), |index| {
+            tone_map(self.mean(&self.beauty, index).sampled(1))
+        })
+    }
+
+    /// Save everything summed up so far to `file_name', to be
+    /// continued later.  `key' tells apart the settings the film was
+    /// rendered with, `samples' is how many samples per pixel it got.
+    pub fn save_checkpoint(
+        &self,
+        file_name: &str,
+        key: &str,
+        samples: usize,
+    ) -> std::io::Result<()> {
+        write_file(file_name, |out| {
+            let mut out = BufWriter::new(out);
+            out.write_all(CHECKPOINT_MAGIC)?;
+            write_u64(&mut out, key.len() as u64)?;
+            out.write_all(key.as_bytes())?;
+            write_u64(&mut out, samples as u64)?;
+            write_u64(&mut out, self.dropped as u64)?;
+            for color in self.beauty.iter().chain(self.layers.iter().flatten()) {
+                for channel in [color.r(), color.g(), color.b()] {
+                    write_f64(&mut out, channel)?;
+                }
+            }
+            for index in 0..self.weights.len() {
+                write_f64(&mut out, self.weights[index])?;
+                write_u64(&mut out, self.samples[index] as u64)?;
+                write_f64(&mut out, self.mean[index])?;
+                write_f64(&mut out, self.m2[index])?;
+            }
+            out.flush()
+        })
+    }
+
+    /// Load a film of `size' with `aovs' saved by `save_checkpoint',
+    /// with the samples per pixel it got.  `None' if it was saved with
+    /// another `key'.
+    pub fn load_checkpoint(
+        file_name: &str,
+        key: &str,
+        size: Rect,
+        aovs: &[Aov],
+    ) -> std::io::Result<Option<(Film, usize)>> {
+        let mut input = BufReader::new(std::fs::File::open(file_name)?);
+        let mut magic = [0; CHECKPOINT_MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Ok(None);
+        }
+        let key_len = read_u64(&mut input)?;
+        if key_len > MAX_CHECKPOINT_KEY {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("checkpoint key of {} bytes", key_len),
+            ));
+        }
+        let mut saved_key = vec![0; key_len as usize];
+        input.read_exact(&mut saved_key)?;
+        if saved_key != key.as_bytes() {
+            return Ok(None);
+        }
+
+        let mut film = Film::new(size, aovs);
+        let samples = read_u64(&mut input)? as usize;
+        film.dropped = read_u64(&mut input)? as usize;
+        for color in film
+            .beauty
+            .iter_mut()
+            .chain(film.layers.iter_mut().flatten())
+        {
+            let (r, g, b) = (
+                read_f64(&mut input)?,
+                read_f64(&mut input)?,
+                read_f64(&mut input)?,
+            );
+            *color = Color::rgb(r, g, b);
+        }
+        for index in 0..film.weights.len() {
+            film.weights[index] = read_f64(&mut input)?;
+            film.samples[index] = read_u64(&mut input)? as usize;
+            film.mean[index] = read_f64(&mut input)?;
+            film.m2[index] = read_f64(&mut input)?;
+        }
+        Ok(Some((film, samples)))
+    }
+
+    /// Write the number of samples of every pixel to `file_name', from
+    /// black for none to white for `max'.
+    pub fn write_sample_heatmap(&self, file_name: &str, max: usize) -> std::io::Result<()> {
+        self.write_pixels(file_name, |index| {
+            let t = (self.samples[index] as f64 / max.max(1) as f64).min(1.0);
+            // black, red, yellow, white
+            Color::rgb(
+                (3.0 * t).min(1.0),
+                (3.0 * t - 1.0).clamp(0.0, 1.0),
+                (3.0 * t - 2.0).clamp(0.0, 1.0),
+            )
+        })
+    }
+
+    /// Write an image of the film's size like the beauty, from the
+    /// light reaching every pixel instead of sums.
+    pub fn write_like_beauty(&self, file_name: &str, pixels: &[Color]) -> std::io::Result<()> {
+        self.write_pixels(file_name, |index| tone_map(pixels[index].sampled(1)))
+    }
+
+    fn write_pixels<F>(&self, file_name: &str, color_at: F) -> std::io::Result<()>
+    where
+        F: Fn(usize) -> Color,
+    {
+        let ppm = Ppm::new(self.size.clone());
+        let width = self.size.width;
+        write_file(file_name, |out| {
+            // `Rect' doubles as pixel coordinates
+            ppm.write(out, |pixel: Rect| {
+                color_at(pixel.height * width + pixel.width)
+            })
+        })
+    }
+}
+
+/// Start of checkpoint files, with the version of their layout.
+const CHECKPOINT_MAGIC: &[u8; 8] = b"rtiawcp1";
+/// Longest key a checkpoint is read with, longer ones are from broken
+/// files.
+const MAX_CHECKPOINT_KEY: u64 = 4096;
+
+/// Write next to `file_name' first and move it there when done, so an
+/// interrupted render doesn't leave a file cut short.
+fn write_file<F>(file_name: &str, write: F) -> std::io::Result<()>
+where
+    F: FnOnce(&mut std::fs::File) -> std::io::Result<()>,
+{
+    let partial_name = format!("{}.partial", file_name);
+    let mut out_file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&partial_name)?;
+    write(&mut out_file)?;
+    std::fs::rename(partial_name, file_name)
+}
+
+fn write_u64<W: Write>(out: &mut W, value: u64) -> std::io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_f64<W: Write>(out: &mut W, value: f64) -> std::io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_u64<R: Read>(input: &mut R) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(input: &mut R) -> std::io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn aov_names_round_trip() {
+        for aov in Aov::ALL {
+            assert_eq!(Aov::from_name(aov.name()), Some(aov));
+        }
+        assert_eq!(Aov::from_name("beauty"), None);
+    }
+
+    #[test]
+    pub fn running_error_matches_direct_computation() {
+        let mut film = Film::new(
+            Rect {
+                width: 1,
+                height: 1,
+            },
+            &[],
+        );
+        let values = [0.5, 1.5, 0.75, 1.25, 1.0, 2.0];
+        for value in values {
+            film.add(0, 0, 1.0, Color::rgb(value, value, value), &[]);
+        }
+
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let expected = (variance / n).sqrt() / mean;
+        assert!((film.relative_error(0) - expected).abs() < 1e-12);
+
+        let adaptive = SampleCount::Adaptive {
+            min: 2,
+            max: 100,
+            max_error: expected * 1.01,
+        };
+        assert!(adaptive.is_done(&film, 0));
+        assert!(!SampleCount::Fixed(7).is_done(&film, 0));
+    }
+
+    #[test]
+    pub fn checkpoints_round_trip() {
+        let size = Rect {
+            width: 3,
+            height: 2,
+        };
+        let aovs = [Aov::Depth];
+        let mut film = Film::new(size.clone(), &aovs);
+        for i in 0..12 {
+            let value = i as f64 * 0.25;
+            let color = Color::rgb(value, 1.0 - value, 0.5);
+            film.add(i % 3, i % 2, 0.5 + value, color, &[color.scale(2.0)]);
+        }
+        film.add(0, 0, 1.0, Color::rgb(f64::NAN, 0.0, 0.0), &[]);
+
+        let path = std::env::temp_dir().join(format!("rt_iaw_film_{}", std::process::id()));
+        let file_name = path.to_str().unwrap();
+        film.save_checkpoint(file_name, "key", 4).unwrap();
+        let loaded = Film::load_checkpoint(file_name, "key", size.clone(), &aovs).unwrap();
+        let other_key = Film::load_checkpoint(file_name, "other", size.clone(), &aovs).unwrap();
+
+        // a key too long to be read
+        let mut broken = CHECKPOINT_MAGIC.to_vec();
+        broken.extend_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(file_name, broken).unwrap();
+        let broken = Film::load_checkpoint(file_name, "key", size, &aovs);
+        assert!(matches!(broken, Err(error) if error.kind() == std::io::ErrorKind::InvalidData));
+        std::fs::remove_file(file_name).unwrap();
+
+        let (loaded, samples) = loaded.unwrap();
+        assert_eq!(samples, 4);
+        assert_eq!(loaded.dropped(), 1);
+        assert_eq!(loaded.samples(), film.samples());
+        let channels = |c: Color| (c.r(), c.g(), c.b());
+        for index in 0..6 {
+            assert_eq!(channels(loaded.beauty[index]), channels(film.beauty[index]));
+            assert_eq!(
+                channels(loaded.layers[0][index]),
+                channels(film.layers[0][index])
+            );
+            assert_eq!(loaded.relative_error(index), film.relative_error(index));
+        }
+        assert!(other_key.is_none());
+    }
+}
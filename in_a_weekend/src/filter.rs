@@ -0,0 +1,211 @@
+//! Reconstruction filters for pixel samples, used with filter
+//! importance sampling: sample offsets from the pixel center are drawn
+//! in proportion to the filter, so every sample only counts for its own
+//! pixel.  Filters with negative lobes give samples there a negative
+//! weight, with few samples that can leave pixels dark next to bright
+//! ones.  After "Filter Importance Sampling" by Ernst et al.
+
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    /// With `B = C = 1/3'.
+    Mitchell,
+    BlackmanHarris,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::BlackmanHarris,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::BlackmanHarris => "blackman-harris",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// Radius in pixels used if none is given.
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell | FilterKind::BlackmanHarris => 2.0,
+        }
+    }
+
+    /// Value of the filter along one axis at `x' in `[-radius, radius]'.
+    fn evaluate(&self, x: f64, radius: f64) -> f64 {
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+        match self {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => radius - x,
+            FilterKind::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                // goes to zero at the radius
+                gaussian(x) - gaussian(radius)
+            }
+            FilterKind::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            FilterKind::BlackmanHarris => {
+                let n = 0.5 + 0.5 * x / radius;
+                0.35875 - 0.48829 * (2.0 * PI * n).cos() + 0.14128 * (4.0 * PI * n).cos()
+                    - 0.01168 * (6.0 * PI * n).cos()
+            }
+        }
+    }
+}
+
+/// Bins the filter is tabulated in along an axis.
+const TABLE_SIZE: usize = 256;
+
+/// Separable filter tabulated for sampling.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: f64,
+    /// Filter values in the bins of `[-radius, radius]'.
+    values: Vec<f64>,
+    /// Cumulative sums of the absolute values, normalized to end at 1.
+    cdf: Vec<f64>,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: f64) -> Self {
+        let bin_width = 2.0 * radius / TABLE_SIZE as f64;
+        let values: Vec<f64> = (0..TABLE_SIZE)
+            .map(|bin| kind.evaluate(-radius + (bin as f64 + 0.5) * bin_width, radius))
+            .collect();
+        let mut cdf: Vec<f64> = values
+            .iter()
+            .scan(0.0, |sum, value| {
+                *sum += value.abs();
+                Some(*sum)
+            })
+            .collect();
+        let total = cdf.last().copied().unwrap_or(0.0);
+        for c in cdf.iter_mut() {
+            *c /= total;
+        }
+        Self {
+            kind,
+            radius,
+            values,
+            cdf,
+        }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Offset from the pixel center in pixels and the weight of the
+    /// sample, from `u1' and `u2' uniform in `[0, 1)'.
+    pub fn sample(&self, u1: f64, u2: f64) -> (f64, f64, f64) {
+        let (x, x_sign) = self.sample_axis(u1);
+        let (y, y_sign) = self.sample_axis(u2);
+        (x, y, x_sign * y_sign)
+    }
+
+    fn sample_axis(&self, u: f64) -> (f64, f64) {
+        let bin = self.cdf.partition_point(|&c| c <= u).min(TABLE_SIZE - 1);
+        let start = if bin == 0 { 0.0 } else { self.cdf[bin - 1] };
+        let width = self.cdf[bin] - start;
+        let within = if width > 0.0 {
+            (u - start) / width
+        } else {
+            0.5
+        };
+        let bin_width = 2.0 * self.radius / TABLE_SIZE as f64;
+        let x = -self.radius + (bin as f64 + within) * bin_width;
+        (x, self.values[bin].signum())
+    }
+}
+
+impl Default for Filter {
+    /// Box filter over the pixel, as if it was jittered.
+    fn default() -> Self {
+        Filter::new(FilterKind::Box, FilterKind::Box.default_radius())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    pub fn weighted_samples_follow_the_filter() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for kind in FilterKind::ALL {
+            let filter = Filter::new(kind, kind.default_radius());
+            let radius = filter.radius();
+
+            // second moment of the normalized filter by numeric
+            // integration
+            let steps = 10_000;
+            let dx = 2.0 * radius / steps as f64;
+            let (mut integral, mut moment) = (0.0, 0.0);
+            for step in 0..steps {
+                let x = -radius + (step as f64 + 0.5) * dx;
+                integral += kind.evaluate(x, radius) * dx;
+                moment += x * x * kind.evaluate(x, radius) * dx;
+            }
+            let expected = moment / integral;
+
+            let (mut weights, mut weighted) = (0.0, 0.0);
+            for _ in 0..200_000 {
+                let (x, _, weight) = filter.sample(rng.gen(), rng.gen());
+                assert!(x.abs() <= radius);
+                weights += weight;
+                weighted += weight * x * x;
+            }
+            let sampled = weighted / weights;
+            assert!(
+                (sampled - expected).abs() < 0.02 * expected.max(0.1),
+                "{:?}: {} against {}",
+                kind,
+                sampled,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use super::{material::Material, HitRecord, Hittable};
+use crate::{
+    animation::{Animatable, Track},
+    p3::Point3,
+    ray::Ray,
+    v3::Vec3,
+};
+
+/// Rotation around the `y' axis (in radians) followed by a
+/// translation, taking objects into the world.
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    translation: Vec3,
+    rotation_y: f64,
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation_y: f64) -> Self {
+        Self {
+            translation,
+            rotation_y,
+        }
+    }
+
+}
+
+impl Animatable for Transform {
+    fn add(self, other: Self) -> Self {
+        Self::new(
+            self.translation + other.translation,
+            self.rotation_y + other.rotation_y,
+        )
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Self::new(self.translation.scale(factor), self.rotation_y * factor)
+    }
+
+    fn zero() -> Self {
+        Self::new(Vec3::zero(), 0.0)
+    }
+
+    /// Translation and rotation, `x,y,z,angle'.
+    fn from_components(components: &[f64]) -> Option<Self> {
+        match components {
+            [x, y, z, rotation_y] => Some(Self::new(Vec3::new(*x, *y, *z), *rotation_y)),
+            _ => None,
+        }
+    }
+}
+
+fn rotate_y(v: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    let (x, y, z) = v.xyz();
+    Vec3::new(cos * x + sin * z, y, -sin * x + cos * z)
+}
+
+/// Places an object in the world with an animated transform.  The
+/// transform is evaluated at the time of each ray.
+pub struct Instance {
+    object: Arc<dyn Hittable + Send + Sync>,
+    transform: Track<Transform>,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, transform: Track<Transform>) -> Self {
+        Self { object, transform }
+    }
+
+    pub fn new_arc(
+        object: Arc<dyn Hittable + Send + Sync>,
+        transform: Track<Transform>,
+    ) -> Arc<Self> {
+        Arc::new(Self::new(object, transform))
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let transform = self.transform.at(ray.time());
+        // move the ray into object space
+        let origin = ray.origin() - Point3::zero() - transform.translation;
+        let origin = Point3::zero() + rotate_y(origin, -transform.rotation_y);
+        let dir = rotate_y(ray.dir(), -transform.rotation_y);
+        let local_ray = ray.spawn(origin, dir);
+
+        let mut rec = self.object.hit(&local_ray, t_min, t_max)?;
+        // and the hit back into world space
+        rec.point = ray.at(rec.scale);
+        rec.normal = rotate_y(rec.normal, transform.rotation_y);
+        Some(rec)
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material + Send + Sync>> {
+        self.object.materials()
+    }
+}
@@ -1,6 +1,7 @@
-use super::Hittable;
+use super::{material::Material, Hittable};
 use std::{sync::Arc, vec::Vec};
 
+#[derive(Default)]
 pub struct HittableList {
     hittables: Vec<Arc<dyn Hittable + Send + Sync>>,
 }
@@ -26,14 +27,21 @@ impl Hittable for HittableList {
         let mut closest = t_max;
         let mut closest_hit = None;
 
-        for object in self.hittables.iter() {
+        for (object_id, object) in self.hittables.iter().enumerate() {
             // closest is passed as `t_max'
             if let Some(rec) = object.as_ref().hit(ray, t_min, closest) {
                 closest = rec.scale;
-                closest_hit = Some(rec);
+                closest_hit = Some(rec.with_object_id(object_id));
             }
         }
 
         closest_hit
     }
+
+    fn materials(&self) -> Vec<Arc<dyn Material + Send + Sync>> {
+        self.hittables
+            .iter()
+            .flat_map(|object| object.materials())
+            .collect()
+    }
 }
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use super::{Material, Scatter};
+use crate::{
+    color::Color, hittable::HitRecord, ray::Ray, sampler::Sampler, texture::Texture, v3::Vec3,
+};
+
+/// Cuts out parts of a surface, for leaves, fences or decals.  The
+/// luminance of the mask is the opacity.
+pub struct AlphaMask {
+    material: Arc<dyn Material + Send + Sync>,
+    mask: Arc<dyn Texture + Send + Sync>,
+}
+
+impl AlphaMask {
+    pub fn new_arc(
+        material: Arc<dyn Material + Send + Sync>,
+        mask: Arc<dyn Texture + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self { material, mask })
+    }
+}
+
+impl Material for AlphaMask {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        self.material.scatter(ray, rec, sampler)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let (u, v) = rec.uv();
+        let alpha = self.mask.value(u, v, rec.point()).luminance();
+        alpha.clamp(0.0, 1.0) * self.material.opacity(rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<Color> {
+        self.material.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.material.pdf(rec, wo, wi)
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use super::{
+    dielectric::fresnel_dielectric,
+    microfacet::{
+        alpha_from_roughness, g1, g2, reflection, reflection_normal, reflection_pdf,
+        sample_visible_normal, VisibleNormalMean, SMOOTH_ALPHA,
+    },
+    reflect, Material, Scatter,
+};
+use crate::{color::Color, hittable::HitRecord, onb::Onb, ray::Ray, sampler::Sampler, v3::Vec3};
+
+/// Thin clear coat over another material, like lacquer or car paint.
+///
+/// Light is reflected by the coat with the Fresnel reflectance,
+/// otherwise it goes on to the base and gets tinted by the coat.
+/// Picking between the two with the reflectance as probability keeps
+/// the energy below what arrives.
+///
+/// Only a rough coat over a base that can be evaluated can be
+/// evaluated, a smooth coat is treated as specular.
+pub struct Coated {
+    base: Arc<dyn Material + Send + Sync>,
+    refraction_index: f64,
+    alpha: f64,
+    /// What is left of light passing through the coat to the base and
+    /// back.
+    tint: Color,
+    /// Chance of the coat reflecting light, over the cosine of where
+    /// it comes from.
+    reflectance: VisibleNormalMean,
+}
+
+impl Coated {
+    pub fn new_arc(
+        base: Arc<dyn Material + Send + Sync>,
+        refraction_index: f64,
+        roughness: f64,
+        tint: Color,
+    ) -> Arc<Self> {
+        let alpha = alpha_from_roughness(roughness);
+        Arc::new(Self {
+            base,
+            refraction_index,
+            alpha,
+            tint,
+            reflectance: VisibleNormalMean::new(alpha, |wo, m| {
+                fresnel_dielectric(Vec3::dot(wo, m), 1.0 / refraction_index)
+            }),
+        })
+    }
+
+    /// BSDF and density of sampling the coat and the base under it
+    /// together, for `wo' outside.  `None' if either is specular.
+    fn layers(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<(Color, f64)> {
+        if self.alpha < SMOOTH_ALPHA {
+            return None;
+        }
+        let base = self.base.eval(rec, wo, wi)?;
+        let base_pdf = self.base.pdf(rec, wo, wi);
+
+        let onb = Onb::from_w(rec.normal());
+        let (wo, wi) = (onb.to_local(wo), onb.to_local(wi));
+        let through = 1.0 - self.reflectance.at(wo.z());
+        let (coat, coat_pdf) = match reflection_normal(wo, wi) {
+            Some(m) => {
+                let reflectance = fresnel_dielectric(Vec3::dot(wo, m), 1.0 / self.refraction_index);
+                (
+                    reflectance * reflection(wo, wi, m, self.alpha),
+                    reflectance * reflection_pdf(wo, m, self.alpha),
+                )
+            }
+            None => (0.0, 0.0),
+        };
+        Some((
+            Color::rgb(coat, coat, coat) + through * self.tint * base,
+            coat_pdf + through * base_pdf,
+        ))
+    }
+
+    /// Light from `wo' inside only meets the base.
+    fn is_inside(&self, rec: &HitRecord, wo: Vec3) -> bool {
+        !rec.front_face() || Vec3::dot(wo, rec.normal()) <= 0.0
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        if !rec.front_face() {
+            // the coat has no thickness, nothing to do from the inside
+            return self.base.scatter(ray, rec, sampler);
+        }
+
+        let onb = Onb::from_w(rec.normal());
+        let wo = onb.to_local(-ray.dir().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let (u1, u2) = sampler.get_2d();
+        let u = sampler.get_1d();
+        let smooth = self.alpha < SMOOTH_ALPHA;
+        let m = if smooth {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            sample_visible_normal(wo, self.alpha, u1, u2)
+        };
+        let reflectance = fresnel_dielectric(Vec3::dot(wo, m), 1.0 / self.refraction_index);
+
+        if u >= reflectance {
+            let scatter = self.base.scatter(ray, rec, sampler)?;
+            return Some(Scatter {
+                attenuation: self.tint * scatter.attenuation,
+                specular: smooth || scatter.specular,
+                ..scatter
+            });
+        }
+
+        let wi = reflect(-wo, m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let shadowing = if smooth {
+            1.0
+        } else {
+            g2(wo, wi, self.alpha) / g1(wo, self.alpha)
+        };
+        let wi_world = onb.to_world(wi);
+        // `layers' can't evaluate a smooth coat or a specular base
+        let base = self.base.eval(rec, -ray.dir().normalized(), wi_world);
+        Some(Scatter {
+            scattered_ray: ray.spawn(rec.point(), wi_world),
+            attenuation: Color::rgb(shadowing, shadowing, shadowing),
+            specular: smooth || base.is_none(),
+            in_medium: false,
+        })
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let coat = fresnel_dielectric(1.0, 1.0 / self.refraction_index);
+        Color::rgb(coat, coat, coat) + (1.0 - coat) * self.tint * self.base.albedo(rec)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<Color> {
+        if self.is_inside(rec, wo) {
+            return self.base.eval(rec, wo, wi);
+        }
+        self.layers(rec, wo, wi).map(|(f, _)| f)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if self.is_inside(rec, wo) {
+            return self.base.pdf(rec, wo, wi);
+        }
+        self.layers(rec, wo, wi).map_or(0.0, |(_, pdf)| pdf)
+    }
+}
@@ -1,52 +1,176 @@
 use std::sync::Arc;
-use rand::Rng;
 
-use crate::{color::Color, hittable::HitRecord, ray::Ray, v3::Vec3};
+use crate::{color::Color, hittable::HitRecord, ray::Ray, sampler::Sampler, v3::Vec3};
 
 use super::{reflect, Material, Scatter};
 
 pub struct Dielectric {
-    refraction_index: f64,
+    refraction_index: RefractiveIndex,
+    absorption: Color,
+}
+
+/// Index of refraction, possibly depending on the wavelength.
+/// Wavelengths are in nanometres.
+#[derive(Clone, Copy, Debug)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    /// `n = a + b / λ²' with `λ' in micrometres.
+    Cauchy { a: f64, b: f64 },
+    /// `n² = 1 + Σ bᵢλ² / (λ² - cᵢ)' with `λ' in micrometres.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+/// Wavelength used when not rendering spectrally, the sodium D line.
+const REFERENCE_WAVELENGTH: f64 = 589.3;
+
+impl RefractiveIndex {
+    pub fn bk7() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.1750 * 0.1750, 0.1060 * 0.1060, 0.0],
+        }
+    }
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        let micrometres = lambda / 1000.0;
+        let l2 = micrometres * micrometres;
+        match self {
+            RefractiveIndex::Constant(n) => *n,
+            RefractiveIndex::Cauchy { a, b } => a + b / l2,
+            RefractiveIndex::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c.iter()).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
 }
 
 impl Dielectric {
     pub fn new_arc(refraction_index: f64) -> Arc<Self> {
-        Arc::new(Self { refraction_index })
+        Self::absorbing_arc(refraction_index, Color::rgb(0.0, 0.0, 0.0))
+    }
+
+    /// `absorption' is the absorption coefficient per unit length
+    /// for each channel.
+    pub fn absorbing_arc(refraction_index: f64, absorption: Color) -> Arc<Self> {
+        Arc::new(Self {
+            refraction_index: RefractiveIndex::Constant(refraction_index),
+            absorption,
+        })
+    }
+
+    /// Dispersion only shows with the spectral integrator.
+    pub fn dispersive_arc(refraction_index: RefractiveIndex) -> Arc<Self> {
+        Arc::new(Self {
+            refraction_index,
+            absorption: Color::rgb(0.0, 0.0, 0.0),
+        })
+    }
+
+    /// Light travelling `distance' inside the medium is left with
+    /// `transmittance' of itself.  Channels of `transmittance' are
+    /// clamped to `(0, 1]', `distance' to at least
+    /// `MIN_TINT_DISTANCE'.
+    pub fn tinted_arc(refraction_index: f64, transmittance: Color, distance: f64) -> Arc<Self> {
+        Self::absorbing_arc(refraction_index, absorption_for(transmittance, distance))
     }
 }
 
+/// Shortest distance a tint can be given for, a zero one would make
+/// the absorption infinite.
+pub const MIN_TINT_DISTANCE: f64 = 1e-6;
+
+pub(super) fn absorption_for(transmittance: Color, distance: f64) -> Color {
+    let distance = distance.max(MIN_TINT_DISTANCE);
+    // more than all of the light can't come through
+    let coefficient = |t: f64| -t.clamp(f64::MIN_POSITIVE, 1.0).ln() / distance;
+    Color::rgb(
+        coefficient(transmittance.r()),
+        coefficient(transmittance.g()),
+        coefficient(transmittance.b()),
+    )
+}
+
+/// Beer-Lambert attenuation for the segment of `ray' up to the hit.
+/// Only applies when the hit is from the inside, as that segment ran
+/// through the medium.
+pub(super) fn beer_lambert(absorption: Color, ray: &Ray, rec: &HitRecord) -> Color {
+    if rec.front_face() {
+        return Color::rgb(1.0, 1.0, 1.0);
+    }
+    let distance = rec.scale() * ray.dir().size();
+    let transmittance = |a: f64| (-a * distance).exp();
+    Color::rgb(
+        transmittance(absorption.r()),
+        transmittance(absorption.g()),
+        transmittance(absorption.b()),
+    )
+}
+
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<Scatter> {
-        let attenuation = Color::rgb(1.0, 1.0, 1.0);
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let attenuation = beer_lambert(self.absorption, ray, rec);
+
+        // a dispersive medium sends each wavelength in a different
+        // direction, only the hero one can be followed
+        let wavelengths = ray
+            .wavelengths()
+            .filter(|_| self.refraction_index.is_dispersive())
+            .map(|w| w.terminate_secondary());
+        let lambda = wavelengths.map_or(REFERENCE_WAVELENGTH, |w| w.hero());
+        let refraction_index = self.refraction_index.at(lambda);
 
         let refraction_ratio = if rec.front_face() {
-            1.0 / self.refraction_index
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
         let unit_ray_direction = ray.dir().normalized();
+        let u = sampler.get_1d();
         let ray_direction = match refract(unit_ray_direction, rec.normal(), refraction_ratio)
-            .filter(|_ray| !should_reflect(unit_ray_direction, rec.normal(), refraction_ratio))
+            .filter(|_ray| !should_reflect(unit_ray_direction, rec.normal(), refraction_ratio, u))
         {
             Some(refracted_ray) => refracted_ray,
             None => reflect(unit_ray_direction, rec.normal()),
         };
 
-        let scattered_ray = Ray::new(rec.point(), ray_direction);
+        let scattered_ray = ray.spawn(rec.point(), ray_direction);
+        let scattered_ray = match wavelengths {
+            Some(wavelengths) => scattered_ray.with_wavelengths(wavelengths),
+            None => scattered_ray,
+        };
         Some(Scatter {
             scattered_ray,
             attenuation,
+            specular: true,
+            in_medium: false,
         })
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::rgb(1.0, 1.0, 1.0)
+    }
 }
 
-fn should_reflect(ray_dir: Vec3, normal: Vec3, refraction_ratio: f64) -> bool {
+/// With `u' uniform in `[0, 1)'.
+fn should_reflect(ray_dir: Vec3, normal: Vec3, refraction_ratio: f64, u: f64) -> bool {
     let cos_theta = Vec3::dot(ray_dir, normal.scale(-1.0));
-    reflectance(cos_theta, refraction_ratio) > rand::thread_rng().gen()
+    reflectance(cos_theta, refraction_ratio) > u
 }
 
-fn reflectance(cos_theta: f64, refraction_ratio: f64) -> f64 {
+pub(super) fn reflectance(cos_theta: f64, refraction_ratio: f64) -> f64 {
     // Use Schlick's approximation
     let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
     let res = r0 + (1.0 - r0) * (1.0 - cos_theta).max(0.0).powi(5);
@@ -56,7 +180,21 @@ fn reflectance(cos_theta: f64, refraction_ratio: f64) -> f64 {
     res
 }
 
-fn refract(unit_ray_direction: Vec3, normal: Vec3, refraction_ratio: f64) -> Option<Vec3> {
+/// Exact Fresnel reflectance of an interface between two dielectrics.
+pub(super) fn fresnel_dielectric(cos_theta: f64, refraction_ratio: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = refraction_ratio * refraction_ratio * (1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (refraction_ratio * cos_i - cos_t) / (refraction_ratio * cos_i + cos_t);
+    let rp = (cos_i - refraction_ratio * cos_t) / (cos_i + refraction_ratio * cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+pub(super) fn refract(unit_ray_direction: Vec3, normal: Vec3, refraction_ratio: f64) -> Option<Vec3> {
     let cos_theta_one = Vec3::dot(unit_ray_direction, normal.scale(-1.0));
     if cos_theta_one > 1.0 {
         return None;
@@ -72,3 +210,31 @@ fn refract(unit_ray_direction: Vec3, normal: Vec3, refraction_ratio: f64) -> Opt
             + normal.scale(refraction_ratio * cos_theta_one - cos_theta_two),
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn sellmeier_matches_known_indices() {
+        let d_line = REFERENCE_WAVELENGTH;
+        assert!((RefractiveIndex::bk7().at(d_line) - 1.5168).abs() < 1e-3);
+        assert!((RefractiveIndex::diamond().at(d_line) - 2.417).abs() < 1e-2);
+        // blue bends more than red
+        let bk7 = RefractiveIndex::bk7();
+        assert!(bk7.at(450.0) > bk7.at(650.0));
+    }
+
+    #[test]
+    pub fn tints_are_clamped() {
+        let channels = |c: Color| [c.r(), c.g(), c.b()];
+        for distance in [0.0, -1.0, f64::NAN] {
+            let absorption = absorption_for(Color::rgb(0.5, 0.5, 0.5), distance);
+            let positive = |a: &f64| a.is_finite() && *a > 0.0;
+            assert!(channels(absorption).iter().all(positive));
+        }
+        let absorption = absorption_for(Color::rgb(0.0, 1.0, 2.0), 1.0);
+        assert!(absorption.r().is_finite() && absorption.r() > 0.0);
+        assert_eq!(channels(absorption)[1..], [0.0, 0.0]);
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use super::{Material, Scatter};
+use crate::{color::Color, hittable::HitRecord, ray::Ray, sampler::Sampler};
+
+/// Surface giving off light evenly in all directions from its front.
+pub struct DiffuseLight {
+    emission: Color,
+}
+
+impl DiffuseLight {
+    pub fn new_arc(emission: Color) -> Arc<Self> {
+        Arc::new(Self { emission })
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face() {
+            self.emission
+        } else {
+            Color::rgb(0.0, 0.0, 0.0)
+        }
+    }
+}
@@ -1,6 +1,14 @@
-use super::{random_unit_vector, Material, Scatter};
-use crate::{color::Color, hittable::HitRecord, ray::Ray};
-use std::sync::Arc;
+use super::{Material, Scatter};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::{cosine_hemisphere, cosine_hemisphere_pdf},
+    v3::Vec3,
+};
+use std::{f64::consts::PI, sync::Arc};
 
 pub struct Lambertian {
     albedo: Color,
@@ -13,20 +21,34 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray: &Ray, rec: &HitRecord) -> Option<Scatter> {
-        // scattering is random so the incoming ray is not used
-        let mut scatter_direction = rec.normal() + random_unit_vector();
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        // scattering is random so the incoming direction is not used
+        let local = cosine_hemisphere(sampler.get_2d());
+        let scatter_direction = Onb::from_w(rec.normal()).to_world(local);
 
-        // Catch degenerate scatter directions
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal();
-        }
-
-        let scattered_ray = Ray::new(rec.point(), scatter_direction);
+        let scattered_ray = ray.spawn(rec.point(), scatter_direction);
         let attenuation = self.albedo;
         Some(Scatter {
             scattered_ray,
             attenuation,
+            specular: false,
+            in_medium: false,
         })
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn eval(&self, rec: &HitRecord, _wo: Vec3, wi: Vec3) -> Option<Color> {
+        if Vec3::dot(wi, rec.normal()) <= 0.0 {
+            return Some(Color::rgb(0.0, 0.0, 0.0));
+        }
+        Some(self.albedo.scale(1.0 / PI))
+    }
+
+    fn pdf(&self, rec: &HitRecord, _wo: Vec3, wi: Vec3) -> f64 {
+        let onb = Onb::from_w(rec.normal());
+        cosine_hemisphere_pdf(onb.to_local(wi))
+    }
 }
@@ -1,37 +1,273 @@
 use std::sync::Arc;
 
-use super::{random_in_unit_sphere, reflect, Material, Scatter};
-use crate::{color::Color, hittable::HitRecord, ray::Ray, v3::Vec3};
+use super::{
+    microfacet::{
+        alpha_from_roughness, average_albedo, directional_albedo, reflection, reflection_normal,
+        reflection_pdf, sample_visible_normal, SMOOTH_ALPHA,
+    },
+    reflect, Material, Scatter,
+};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::{cosine_hemisphere, cosine_hemisphere_pdf},
+    v3::Vec3,
+};
 
+/// Rough conductor with a GGX microfacet distribution.
+///
+/// Light that would bounce between the microfacets more than once is
+/// added back as a second, diffuse like lobe, after "Revisiting
+/// Physically Based Shading at Imageworks" by Kulla and Conty.  The
+/// lobe is symmetric in both directions, so the BRDF stays
+/// reciprocal, and a white metal reflects all light at any roughness.
 pub struct Metal {
-    albedo: Color,
-    fuzz: f64,
+    fresnel: Fresnel,
+    alpha: f64,
+    /// `average_albedo' of `alpha'.
+    average_albedo: f64,
+    /// Color of the multiple bounce lobe, light loses some of itself
+    /// to the Fresnel term at every bounce.
+    multiple_fresnel: Color,
+}
+
+enum Fresnel {
+    /// Schlick's approximation with the reflectance at normal
+    /// incidence given.
+    Schlick(Color),
+    /// Complex index of refraction `eta + ik' per channel.
+    Conductor { eta: Color, k: Color },
+}
+
+impl Fresnel {
+    fn reflectance(&self, cos_theta: f64) -> Color {
+        match self {
+            Fresnel::Schlick(f0) => {
+                let schlick = |f0: f64| f0 + (1.0 - f0) * (1.0 - cos_theta).max(0.0).powi(5);
+                Color::rgb(schlick(f0.r()), schlick(f0.g()), schlick(f0.b()))
+            }
+            Fresnel::Conductor { eta, k } => Color::rgb(
+                fresnel_conductor(cos_theta, eta.r(), k.r()),
+                fresnel_conductor(cos_theta, eta.g(), k.g()),
+                fresnel_conductor(cos_theta, eta.b(), k.b()),
+            ),
+        }
+    }
+
+    /// Reflectance averaged over the cosine weighted hemisphere.
+    fn average(&self) -> Color {
+        const STEPS: usize = 64;
+        let mut sum = Color::rgb(0.0, 0.0, 0.0);
+        for step in 0..STEPS {
+            let cos_theta = (step as f64 + 0.5) / STEPS as f64;
+            sum += self.reflectance(cos_theta).scale(cos_theta);
+        }
+        sum.scale(2.0 / STEPS as f64)
+    }
+}
+
+/// Unpolarized Fresnel reflectance of a conductor.
+fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
 }
 
 impl Metal {
-    pub fn new_arc(albedo: Color, fuzz: f64) -> Arc<Self> {
+    fn with_fresnel(fresnel: Fresnel, roughness: f64) -> Arc<Self> {
+        let alpha = alpha_from_roughness(roughness);
+        let average_albedo = average_albedo(alpha);
+        // the light bouncing `k' times gets `F_avg^k', summed up over
+        // all of the bounces past the first
+        let f_avg = fresnel.average();
+        let multiple = |f: f64| f * f * average_albedo / (1.0 - f * (1.0 - average_albedo));
+        let multiple_fresnel = Color::rgb(
+            multiple(f_avg.r()),
+            multiple(f_avg.g()),
+            multiple(f_avg.b()),
+        );
         Arc::new(Self {
-            albedo,
-            fuzz: fuzz.clamp(0.0, 1.0),
+            fresnel,
+            alpha,
+            average_albedo,
+            multiple_fresnel,
         })
     }
+
+    /// Simple mode: `albedo' is the color at normal incidence and
+    /// `fuzz' is used as roughness.
+    pub fn new_arc(albedo: Color, fuzz: f64) -> Arc<Self> {
+        Self::with_fresnel(Fresnel::Schlick(albedo), fuzz)
+    }
+
+    pub fn conductor_arc(eta: Color, k: Color, roughness: f64) -> Arc<Self> {
+        Self::with_fresnel(Fresnel::Conductor { eta, k }, roughness)
+    }
+
+    // Indices of refraction sampled at 650, 550 and 450nm.
+
+    pub fn gold_arc(roughness: f64) -> Arc<Self> {
+        Self::conductor_arc(
+            Color::rgb(0.143, 0.374, 1.442),
+            Color::rgb(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper_arc(roughness: f64) -> Arc<Self> {
+        Self::conductor_arc(
+            Color::rgb(0.200, 0.924, 1.102),
+            Color::rgb(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium_arc(roughness: f64) -> Arc<Self> {
+        Self::conductor_arc(
+            Color::rgb(1.657, 0.880, 0.521),
+            Color::rgb(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    /// Chance of sampling the single bounce lobe for light from `wo',
+    /// the multiple bounce lobe gets the rest.
+    fn single_bounce_chance(&self, wo: Vec3) -> f64 {
+        directional_albedo(wo.z(), self.alpha)
+    }
+
+    /// BRDF and density of sampling both lobes, in the frame of the
+    /// surface.
+    fn brdf(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        let black = Color::rgb(0.0, 0.0, 0.0);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return (black, 0.0);
+        }
+        let single_chance = self.single_bounce_chance(wo);
+        let (single, single_pdf) = match reflection_normal(wo, wi) {
+            Some(m) => (
+                reflection(wo, wi, m, self.alpha) * self.fresnel.reflectance(Vec3::dot(wo, m)),
+                reflection_pdf(wo, m, self.alpha),
+            ),
+            None => (black, 0.0),
+        };
+
+        let lost = 1.0 - self.average_albedo;
+        let multiple = if lost > 1e-6 {
+            let lost_from = |w: Vec3| 1.0 - directional_albedo(w.z(), self.alpha);
+            let scale = lost_from(wo) * lost_from(wi) / (std::f64::consts::PI * lost);
+            self.multiple_fresnel.scale(scale)
+        } else {
+            black
+        };
+        (
+            single + multiple,
+            single_chance * single_pdf + (1.0 - single_chance) * cosine_hemisphere_pdf(wi),
+        )
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<Scatter> {
-        let reflected = reflect(ray.dir().normalized(), rec.normal());
-        let scattered_ray = Ray::new(
-            rec.point(),
-            reflected + random_in_unit_sphere().scale(self.fuzz),
-        );
-        let attenuation = self.albedo;
-        if Vec3::dot(scattered_ray.dir(), rec.normal()) > 0.0 {
-            Some(Scatter {
-                scattered_ray,
-                attenuation,
-            })
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let unit_ray_direction = ray.dir().normalized();
+
+        if self.alpha < SMOOTH_ALPHA {
+            let reflected = reflect(unit_ray_direction, rec.normal());
+            let cos_theta = Vec3::dot(reflected, rec.normal());
+            return Some(Scatter {
+                scattered_ray: ray.spawn(rec.point(), reflected),
+                attenuation: self.fresnel.reflectance(cos_theta),
+                specular: true,
+                in_medium: false,
+            });
+        }
+
+        let onb = Onb::from_w(rec.normal());
+        let wo = onb.to_local(-unit_ray_direction);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let u = sampler.get_1d();
+        let (u1, u2) = sampler.get_2d();
+        let wi = if u < self.single_bounce_chance(wo) {
+            let m = sample_visible_normal(wo, self.alpha, u1, u2);
+            reflect(-wo, m)
         } else {
-            None
+            cosine_hemisphere((u1, u2))
+        };
+        if wi.z() <= 0.0 {
+            // reflected into the surface, would bounce off other
+            // microfacets
+            return None;
+        }
+
+        // both lobes could have given `wi', weighted with the balance
+        // heuristic
+        let (f, pdf) = self.brdf(wo, wi);
+        Some(Scatter {
+            scattered_ray: ray.spawn(rec.point(), onb.to_world(wi)),
+            attenuation: f.scale(wi.z() / pdf),
+            specular: false,
+            in_medium: false,
+        })
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.fresnel.reflectance(1.0)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<Color> {
+        if self.alpha < SMOOTH_ALPHA {
+            return None;
         }
+        let onb = Onb::from_w(rec.normal());
+        Some(self.brdf(onb.to_local(wo), onb.to_local(wi)).0)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let onb = Onb::from_w(rec.normal());
+        self.brdf(onb.to_local(wo), onb.to_local(wi)).1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn conductor_fresnel_at_normal_incidence() {
+        let (eta, k) = (0.143, 3.983);
+        let expected = ((eta - 1.0f64).powi(2) + k * k) / ((eta + 1.0f64).powi(2) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-10);
+        assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    pub fn average_fresnel() {
+        // Schlick's approximation averages to `f0 + (1 - f0) / 21'
+        let f0 = 0.3;
+        let average = Fresnel::Schlick(Color::rgb(f0, f0, f0)).average();
+        assert!((average.r() - (f0 + (1.0 - f0) / 21.0)).abs() < 1e-4);
+
+        let white = Metal::new_arc(Color::rgb(1.0, 1.0, 1.0), 0.5);
+        assert!((white.multiple_fresnel.g() - 1.0).abs() < 1e-4);
     }
 }
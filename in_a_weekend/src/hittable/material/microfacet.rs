@@ -0,0 +1,264 @@
+//! Trowbridge-Reitz (GGX) microfacet distribution.  Directions are
+//! in the local frame of the surface with the normal along `z'.
+
+use std::{f64::consts::PI, sync::OnceLock};
+
+use crate::v3::Vec3;
+
+/// Surfaces with smaller `alpha' are treated as perfectly smooth.
+pub const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// Perceptual roughness is squared to get the width of the
+/// distribution.
+pub fn alpha_from_roughness(roughness: f64) -> f64 {
+    let roughness = roughness.clamp(0.0, 1.0);
+    roughness * roughness
+}
+
+fn lambda(w: Vec3, alpha: f64) -> f64 {
+    let cos2 = w.z() * w.z();
+    if cos2 <= 0.0 {
+        return 0.0;
+    }
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    ((1.0 + alpha * alpha * tan2).sqrt() - 1.0) / 2.0
+}
+
+/// Density of microfacet normals `m' per solid angle, projected onto
+/// the macro surface.
+pub fn d(m: Vec3, alpha: f64) -> f64 {
+    let cos2 = m.z() * m.z();
+    if m.z() <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let denominator = cos2 * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+/// Solid angle density of `sample_visible_normal' giving `m'.
+pub fn visible_normal_pdf(wo: Vec3, m: Vec3, alpha: f64) -> f64 {
+    if wo.z() <= 0.0 {
+        return 0.0;
+    }
+    g1(wo, alpha) * Vec3::dot(wo, m).max(0.0) * d(m, alpha) / wo.z()
+}
+
+/// Smith masking for a single direction.
+pub fn g1(w: Vec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + lambda(w, alpha))
+}
+
+/// Height-correlated Smith shadowing-masking.
+pub fn g2(wo: Vec3, wi: Vec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + lambda(wo, alpha) + lambda(wi, alpha))
+}
+
+/// Sample a microfacet normal visible from `wo' with `u1' and `u2'
+/// uniform in `[0, 1)'.
+///
+/// "Sampling the GGX Distribution of Visible Normals" by Heitz.
+pub fn sample_visible_normal(wo: Vec3, alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    // stretch the view direction to the hemisphere configuration
+    let vh = Vec3::new(alpha * wo.x(), alpha * wo.y(), wo.z()).normalized();
+
+    let len_sq = vh.x() * vh.x() + vh.y() * vh.y();
+    let t1 = if len_sq > 0.0 {
+        Vec3::new(-vh.y(), vh.x(), 0.0).scale(1.0 / len_sq.sqrt())
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = Vec3::cross(vh, t1);
+
+    // sample the projected area of the visible hemisphere
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let p2 = r * phi.sin();
+    let s = 0.5 * (1.0 + vh.z());
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+    let nh = t1.scale(p1) + t2.scale(p2) + vh.scale((1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt());
+
+    // and unstretch it
+    Vec3::new(alpha * nh.x(), alpha * nh.y(), nh.z().max(0.0)).normalized()
+}
+
+/// Microfacet normal reflecting `wo' into `wi', both have to be above
+/// the surface.
+pub fn reflection_normal(wo: Vec3, wi: Vec3) -> Option<Vec3> {
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return None;
+    }
+    Some((wo + wi).normalized())
+}
+
+/// BRDF of a white surface reflecting `wo' into `wi' off the
+/// microfacet normal `m' between them, before Fresnel.
+pub fn reflection(wo: Vec3, wi: Vec3, m: Vec3, alpha: f64) -> f64 {
+    d(m, alpha) * g2(wo, wi, alpha) / (4.0 * wo.z() * wi.z())
+}
+
+/// Solid angle density of reflecting `wo' off a visible normal, which
+/// has to be `m' to end up where it did.
+pub fn reflection_pdf(wo: Vec3, m: Vec3, alpha: f64) -> f64 {
+    visible_normal_pdf(wo, m, alpha) / (4.0 * Vec3::dot(wo, m))
+}
+
+/// Microfacet normal refracting `wo' in a medium with index `eta_o'
+/// into `wi' in one with index `eta_i'.  `wo' has to be above the
+/// surface and `wi' below it.
+pub fn refraction_normal(wo: Vec3, wi: Vec3, eta_o: f64, eta_i: f64) -> Option<Vec3> {
+    if wo.z() <= 0.0 || wi.z() >= 0.0 {
+        return None;
+    }
+    let m = -(wo.scale(eta_o) + wi.scale(eta_i));
+    if m.near_zero() {
+        return None;
+    }
+    let m = if m.z() < 0.0 { -m } else { m }.normalized();
+    if Vec3::dot(wo, m) <= 0.0 || Vec3::dot(wi, m) >= 0.0 {
+        return None;
+    }
+    Some(m)
+}
+
+/// Change of solid angle from microfacet normals to refracted
+/// directions.
+fn refraction_jacobian(wo: Vec3, wi: Vec3, m: Vec3, eta_o: f64, eta_i: f64) -> f64 {
+    let denominator = eta_o * Vec3::dot(wo, m) + eta_i * Vec3::dot(wi, m);
+    eta_i * eta_i * Vec3::dot(wi, m).abs() / (denominator * denominator)
+}
+
+/// BTDF of a surface letting all light from `wo' through the
+/// microfacet normal `m' into `wi', before Fresnel.  Like the weights
+/// of the samples, it leaves out the change of radiance across the
+/// boundary.
+///
+/// "Microfacet Models for Refraction through Rough Surfaces" by
+/// Walter et al.
+pub fn transmission(wo: Vec3, wi: Vec3, m: Vec3, eta_o: f64, eta_i: f64, alpha: f64) -> f64 {
+    d(m, alpha)
+        * g2(wo, wi, alpha)
+        * Vec3::dot(wo, m)
+        * refraction_jacobian(wo, wi, m, eta_o, eta_i)
+        / (wo.z() * wi.z().abs())
+}
+
+/// Solid angle density of refracting `wo' through a visible normal,
+/// which has to be `m' to end up at `wi'.
+pub fn transmission_pdf(wo: Vec3, wi: Vec3, m: Vec3, eta_o: f64, eta_i: f64, alpha: f64) -> f64 {
+    visible_normal_pdf(wo, m, alpha) * refraction_jacobian(wo, wi, m, eta_o, eta_i)
+}
+
+/// Resolution of the directional albedo table in both directions.
+const ALBEDO_TABLE_SIZE: usize = 32;
+
+/// Fraction of the light from `wo' a white GGX reflector sends back
+/// after a single bounce, the rest is lost in the microfacets.  From a
+/// table over the cosine of `wo' and `alpha'.
+pub fn directional_albedo(cos_theta: f64, alpha: f64) -> f64 {
+    static TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        // the albedo changes fast with alpha, the nodes include both
+        // ends so nothing is extrapolated
+        let node = |i: usize| i as f64 / (ALBEDO_TABLE_SIZE - 1) as f64;
+        (0..ALBEDO_TABLE_SIZE * ALBEDO_TABLE_SIZE)
+            .map(|i| integrate_albedo(node(i % ALBEDO_TABLE_SIZE), node(i / ALBEDO_TABLE_SIZE)))
+            .collect()
+    });
+
+    // bilinear between the nodes
+    let coordinate = |x: f64| {
+        let x = x.clamp(0.0, 1.0) * (ALBEDO_TABLE_SIZE - 1) as f64;
+        let low = (x as usize).min(ALBEDO_TABLE_SIZE - 2);
+        (low, x - low as f64)
+    };
+    let (x, tx) = coordinate(cos_theta);
+    let (y, ty) = coordinate(alpha);
+    let at = |x: usize, y: usize| table[y * ALBEDO_TABLE_SIZE + x];
+    let bottom = at(x, y) * (1.0 - tx) + at(x + 1, y) * tx;
+    let top = at(x, y + 1) * (1.0 - tx) + at(x + 1, y + 1) * tx;
+    bottom * (1.0 - ty) + top * ty
+}
+
+/// Cosine weighted mean of `directional_albedo' over the hemisphere,
+/// the fraction of light from everywhere reflected after a single
+/// bounce.
+pub fn average_albedo(alpha: f64) -> f64 {
+    let n = 64;
+    let sum: f64 = (0..n)
+        .map(|i| {
+            let cos_theta = (i as f64 + 0.5) / n as f64;
+            directional_albedo(cos_theta, alpha) * cos_theta
+        })
+        .sum();
+    2.0 * sum / n as f64
+}
+
+/// Mean weight of visible normal samples reflecting light from `wo'
+/// with the cosine `cos_theta' off a white surface.
+fn integrate_albedo(cos_theta: f64, alpha: f64) -> f64 {
+    if alpha < SMOOTH_ALPHA {
+        return 1.0;
+    }
+    visible_normal_mean(cos_theta, alpha, &|wo, m| {
+        let wi = m.scale(2.0 * Vec3::dot(wo, m)) - wo;
+        if wi.z() > 0.0 {
+            g2(wo, wi, alpha) / g1(wo, alpha)
+        } else {
+            0.0
+        }
+    })
+}
+
+/// Mean of `f' of `wo' and the microfacet normal over the normals
+/// visible from `wo' with the cosine `cos_theta'.
+fn visible_normal_mean(cos_theta: f64, alpha: f64, f: &dyn Fn(Vec3, Vec3) -> f64) -> f64 {
+    // light from right at the horizon is treated as almost there
+    let cos_theta = cos_theta.max(1e-3);
+    let wo = Vec3::new(
+        (1.0 - cos_theta * cos_theta).max(0.0).sqrt(),
+        0.0,
+        cos_theta,
+    );
+    if alpha < SMOOTH_ALPHA {
+        return f(wo, Vec3::new(0.0, 0.0, 1.0));
+    }
+    let n = 32;
+    let cell = |i: usize| (i as f64 + 0.5) / n as f64;
+    let sum: f64 = (0..n * n)
+        .map(|i| {
+            f(
+                wo,
+                sample_visible_normal(wo, alpha, cell(i % n), cell(i / n)),
+            )
+        })
+        .sum();
+    sum / (n * n) as f64
+}
+
+/// Mean over the visible normals like `visible_normal_mean', for a
+/// single `alpha' and tabulated over the cosine of `wo'.  Gives the
+/// probability of picking a lobe with a chance that depends on the
+/// sampled normal.
+pub struct VisibleNormalMean {
+    table: Vec<f64>,
+}
+
+impl VisibleNormalMean {
+    pub fn new(alpha: f64, f: impl Fn(Vec3, Vec3) -> f64) -> Self {
+        let node = |i: usize| i as f64 / (ALBEDO_TABLE_SIZE - 1) as f64;
+        Self {
+            table: (0..ALBEDO_TABLE_SIZE)
+                .map(|i| visible_normal_mean(node(i), alpha, &f))
+                .collect(),
+        }
+    }
+
+    pub fn at(&self, cos_theta: f64) -> f64 {
+        let x = cos_theta.clamp(0.0, 1.0) * (ALBEDO_TABLE_SIZE - 1) as f64;
+        let low = (x as usize).min(ALBEDO_TABLE_SIZE - 2);
+        let t = x - low as f64;
+        self.table[low] * (1.0 - t) + self.table[low + 1] * t
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use super::{Material, Scatter};
+use crate::{
+    color::Color, hittable::HitRecord, ray::Ray, sampler::Sampler, texture::Texture, v3::Vec3,
+};
+
+/// How much of the second material shows.
+pub enum MixWeight {
+    Constant(f64),
+    /// The luminance of the texture is used.
+    Texture(Arc<dyn Texture + Send + Sync>),
+}
+
+/// Blend of two materials, like rust over metal.
+///
+/// Every scatter is done by one of the two, picked with the weight
+/// as probability.  On average that gives the weighted sum of both.
+/// A specular side adds nothing to the BSDF, the mix is only specular
+/// if both sides are.
+pub struct Mix {
+    first: Arc<dyn Material + Send + Sync>,
+    second: Arc<dyn Material + Send + Sync>,
+    weight: MixWeight,
+}
+
+impl Mix {
+    pub fn new_arc(
+        first: Arc<dyn Material + Send + Sync>,
+        second: Arc<dyn Material + Send + Sync>,
+        weight: MixWeight,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            first,
+            second,
+            weight,
+        })
+    }
+
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        let weight = match &self.weight {
+            MixWeight::Constant(weight) => *weight,
+            MixWeight::Texture(texture) => {
+                let (u, v) = rec.uv();
+                texture.value(u, v, rec.point()).luminance()
+            }
+        };
+        weight.clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        if sampler.get_1d() < self.weight(rec) {
+            self.second.scatter(ray, rec, sampler)
+        } else {
+            self.first.scatter(ray, rec, sampler)
+        }
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.opacity(rec) + weight * self.second.opacity(rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.emitted(rec) + weight * self.second.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.albedo(rec) + weight * self.second.albedo(rec)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<Color> {
+        let weight = self.weight(rec);
+        let black = || Color::rgb(0.0, 0.0, 0.0);
+        match (self.first.eval(rec, wo, wi), self.second.eval(rec, wo, wi)) {
+            (None, None) => None,
+            (first, second) => Some(
+                (1.0 - weight) * first.unwrap_or_else(black)
+                    + weight * second.unwrap_or_else(black),
+            ),
+        }
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.pdf(rec, wo, wi) + weight * self.second.pdf(rec, wo, wi)
+    }
+}
@@ -1,14 +1,29 @@
 use super::HitRecord;
-use crate::{color::Color, ray::Ray, v3::Vec3};
+use crate::{color::Color, ray::Ray, sampler::Sampler, v3::Vec3};
 
+pub mod alpha_mask;
+pub mod coated;
+pub mod dielectric;
+pub mod diffuse_light;
 pub mod lambertian;
 pub mod metal;
-pub mod dielectric;
+pub mod microfacet;
+pub mod mix;
+pub mod principled;
+pub mod rough_dielectric;
+#[cfg(test)]
+mod statistical_tests;
+pub mod subsurface;
 
 #[derive(Debug)]
 pub struct Scatter {
     scattered_ray: Ray,
     attenuation: Color,
+    /// Picked from a delta distribution, or where `eval' and `pdf'
+    /// don't cover it.
+    specular: bool,
+    /// Scattered inside a medium instead of at the surface.
+    in_medium: bool,
 }
 
 impl Scatter {
@@ -19,23 +34,56 @@ impl Scatter {
     pub fn ray(&self) -> &Ray {
         &self.scattered_ray
     }
+
+    /// Integrators can't connect to a vertex that scattered like
+    /// this, nor weigh its direction against other strategies.  Mixed
+    /// materials only have it set when the specular part was picked.
+    pub fn is_specular(&self) -> bool {
+        self.specular
+    }
+
+    /// Steps of a random walk through a medium aren't bounces off a
+    /// surface, integrators don't count them towards the depth.
+    pub fn in_medium(&self) -> bool {
+        self.in_medium
+    }
 }
 
 pub trait Material {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<Scatter>;
-}
+    /// Pick where the light arriving along `ray' goes, with the
+    /// numbers from `sampler'.
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter>;
 
-fn random_unit_vector() -> Vec3 {
-    random_in_unit_sphere().normalized()
-}
+    /// Cut out parts of the surface have 0 opacity.  Rays go through
+    /// them as if nothing was there.
+    fn opacity(&self, _rec: &HitRecord) -> f64 {
+        1.0
+    }
+
+    /// Light given off at the hit towards where the ray came from.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::rgb(0.0, 0.0, 0.0)
+    }
+
+    /// Color of the surface at the hit, without picking a direction.
+    /// What is reflected at normal incidence for specular materials,
+    /// white for clear ones and black for those that only emit.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::rgb(0.0, 0.0, 0.0)
+    }
+
+    /// BSDF for light arriving from `wi' and leaving towards `wo',
+    /// both unit vectors pointing away from the surface.  `None' for
+    /// materials that can only be sampled with `scatter', those are
+    /// treated as specular by integrators connecting paths.
+    fn eval(&self, _rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> Option<Color> {
+        None
+    }
 
-fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let vec = Vec3::random(-1.0, 1.0);
-        // FIXME: less is used in the book.  Isn't equal OK too?
-        if vec.length_squared() < 1.0 {
-            return vec;
-        }
+    /// Solid angle density of `scatter' sending a ray arriving from
+    /// `wo' towards `wi'.  Only meaningful where `eval' is.
+    fn pdf(&self, _rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
     }
 }
 
@@ -0,0 +1,391 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use super::{
+    dielectric::{fresnel_dielectric, refract},
+    microfacet::{
+        alpha_from_roughness, g1, g2, reflection, reflection_normal, reflection_pdf,
+        refraction_normal, sample_visible_normal, transmission, transmission_pdf,
+        VisibleNormalMean, SMOOTH_ALPHA,
+    },
+    reflect,
+    rough_dielectric::{dielectric_frame, rough_boundary},
+    Material, Scatter,
+};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::{cosine_hemisphere, cosine_hemisphere_pdf},
+    v3::Vec3,
+};
+
+/// Parameters of the principled material.  Everything except the
+/// colors and the index of refraction is between 0 and 1, and is
+/// clamped to that by `Principled::new_arc'.
+#[derive(Clone, Copy, Debug)]
+pub struct Params {
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    /// Reflectance of dielectrics at normal incidence, 0.5 is 4%.
+    pub specular: f64,
+    /// Tints the dielectric specular towards the base color.
+    pub specular_tint: f64,
+    /// Extra grazing reflection for cloth.
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    /// Strength of a clear coat with index of refraction 1.5 on top.
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    /// Share of the dielectric base that is transmitted instead of
+    /// diffusely reflected.
+    pub transmission: f64,
+    pub ior: f64,
+}
+
+impl Params {
+    fn clamped(self) -> Self {
+        let unit = |x: f64| x.clamp(0.0, 1.0);
+        Self {
+            metallic: unit(self.metallic),
+            roughness: unit(self.roughness),
+            specular: unit(self.specular),
+            specular_tint: unit(self.specular_tint),
+            sheen: unit(self.sheen),
+            sheen_tint: unit(self.sheen_tint),
+            clearcoat: unit(self.clearcoat),
+            clearcoat_roughness: unit(self.clearcoat_roughness),
+            transmission: unit(self.transmission),
+            ..self
+        }
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            base_color: Color::rgb(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+/// Disney style "principled" material combining diffuse, sheen,
+/// specular, metal, transmission and clear coat lobes.
+///
+/// Each scatter picks a single lobe at random, going from the top
+/// layer down: clear coat, then metal or dielectric, where the
+/// dielectric splits into specular, transmission and diffuse.  Lobes
+/// are picked with the probability of light reaching them, so the
+/// chosen lobe's own sample weight is the full estimate.
+///
+/// A smooth base or a smooth clear coat can't be evaluated, the
+/// material is treated as specular then.
+pub struct Principled {
+    params: Params,
+    alpha: f64,
+    clearcoat_alpha: f64,
+    /// Chances of the clear coat and the dielectric specular being
+    /// picked, over the cosine of where the light comes from.
+    clearcoat_reflectance: VisibleNormalMean,
+    specular_reflectance: VisibleNormalMean,
+}
+
+impl Principled {
+    pub fn new_arc(params: Params) -> Arc<Self> {
+        let params = params.clamped();
+        let alpha = alpha_from_roughness(params.roughness);
+        let clearcoat_alpha = alpha_from_roughness(params.clearcoat_roughness);
+        let f0 = 0.08 * params.specular;
+        Arc::new(Self {
+            params,
+            alpha,
+            clearcoat_alpha,
+            clearcoat_reflectance: VisibleNormalMean::new(clearcoat_alpha, |wo, m| {
+                params.clearcoat * fresnel_dielectric(Vec3::dot(wo, m), 1.0 / 1.5)
+            }),
+            specular_reflectance: VisibleNormalMean::new(alpha, |wo, m| {
+                schlick(f0, Vec3::dot(wo, m))
+            }),
+        })
+    }
+
+    /// Material from glTF `pbrMetallicRoughness' factors.
+    pub fn from_gltf_arc(
+        base_color_factor: Color,
+        metallic_factor: f64,
+        roughness_factor: f64,
+    ) -> Arc<Self> {
+        Self::new_arc(Params {
+            base_color: base_color_factor,
+            metallic: metallic_factor,
+            roughness: roughness_factor,
+            ..Params::default()
+        })
+    }
+
+    /// Hue of the base color at full brightness, no channel is above
+    /// 1 so tinting doesn't add light.
+    fn tint(&self) -> Color {
+        let base = self.params.base_color;
+        let max = base.r().max(base.g()).max(base.b());
+        if max > 0.0 {
+            base.scale(1.0 / max)
+        } else {
+            white()
+        }
+    }
+
+    fn is_specular(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+            || (self.params.clearcoat > 0.0 && self.clearcoat_alpha < SMOOTH_ALPHA)
+    }
+
+    /// BSDF and density of sampling all the lobes together, for `wo'
+    /// above the surface and outside.  Each lobe counts with the
+    /// chance of `scatter' getting to it.
+    fn lobes(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        let p = &self.params;
+        let mut f = Color::rgb(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        let mut add = |chance: f64, lobe: Color, lobe_pdf: f64| {
+            f += chance * lobe;
+            pdf += chance * lobe_pdf;
+        };
+
+        let below_coat = 1.0 - self.clearcoat_reflectance.at(wo.z());
+        let dielectric = below_coat * (1.0 - p.metallic);
+        if let Some(m) = reflection_normal(wo, wi) {
+            let cos_m = Vec3::dot(wo, m);
+            if p.clearcoat > 0.0 {
+                let alpha = self.clearcoat_alpha;
+                let chance = p.clearcoat * fresnel_dielectric(cos_m, 1.0 / 1.5);
+                let lobe = reflection(wo, wi, m, alpha);
+                add(chance, gray(lobe), reflection_pdf(wo, m, alpha));
+            }
+
+            let lobe = reflection(wo, wi, m, self.alpha);
+            let lobe_pdf = reflection_pdf(wo, m, self.alpha);
+            let f0 = p.base_color;
+            let fresnel = Color::rgb(
+                schlick(f0.r(), cos_m),
+                schlick(f0.g(), cos_m),
+                schlick(f0.b(), cos_m),
+            );
+            add(below_coat * p.metallic, lobe * fresnel, lobe_pdf);
+
+            let tint = mix(white(), self.tint(), p.specular_tint);
+            let chance = dielectric * schlick(0.08 * p.specular, cos_m);
+            add(chance, lobe * tint, lobe_pdf);
+        }
+
+        if let Some(m) = refraction_normal(wo, wi, 1.0, p.ior) {
+            let specular = schlick(0.08 * p.specular, Vec3::dot(wo, m));
+            let chance = dielectric * (1.0 - specular) * p.transmission;
+            let lobe = transmission(wo, wi, m, 1.0, p.ior, self.alpha);
+            let lobe_pdf = transmission_pdf(wo, wi, m, 1.0, p.ior, self.alpha);
+            add(chance, lobe * p.base_color, lobe_pdf);
+        }
+
+        if wi.z() > 0.0 {
+            let specular = self.specular_reflectance.at(wo.z());
+            let chance = dielectric * (1.0 - specular) * (1.0 - p.transmission);
+            let half = (wi + wo).normalized();
+            let sheen = p.sheen * (1.0 - Vec3::dot(wi, half)).max(0.0).powi(5);
+            let sheen_color = mix(white(), self.tint(), p.sheen_tint);
+            let lobe = mix(p.base_color, sheen_color, sheen).scale(1.0 / PI);
+            add(chance, lobe, cosine_hemisphere_pdf(wi));
+        }
+        (f, pdf)
+    }
+
+    /// `lobes' from the outside, or the rough boundary `scatter' uses
+    /// from the inside.
+    fn bsdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<(Color, f64)> {
+        if self.is_specular() {
+            return None;
+        }
+        let outside = (Vec3::dot(wo, rec.normal()) >= 0.0) == rec.front_face();
+        let (wo, wi, eta_o, eta_i) = dielectric_frame(rec, wo, wi, self.params.ior);
+        if outside {
+            return Some(self.lobes(wo, wi));
+        }
+        let (f, pdf) = rough_boundary(wo, wi, eta_o, eta_i, self.alpha);
+        Some((gray(f), pdf))
+    }
+}
+
+fn gray(v: f64) -> Color {
+    Color::rgb(v, v, v)
+}
+
+fn white() -> Color {
+    Color::rgb(1.0, 1.0, 1.0)
+}
+
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+fn schlick(f0: f64, cos_theta: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).max(0.0).powi(5)
+}
+
+fn sample_normal(wo: Vec3, alpha: f64, sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    if alpha < SMOOTH_ALPHA {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        sample_visible_normal(wo, alpha, u1, u2)
+    }
+}
+
+fn shadowing(wo: Vec3, wi: Vec3, alpha: f64) -> f64 {
+    if alpha < SMOOTH_ALPHA {
+        1.0
+    } else {
+        g2(wo, wi, alpha) / g1(wo, alpha)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let p = &self.params;
+        let onb = Onb::from_w(rec.normal());
+        let wo = onb.to_local(-ray.dir().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let scattered = |wi: Vec3, attenuation: Color| {
+            Some(Scatter {
+                scattered_ray: ray.spawn(rec.point(), onb.to_world(wi)),
+                attenuation,
+                specular: self.is_specular(),
+                in_medium: false,
+            })
+        };
+
+        if !rec.front_face() {
+            // only transmission lets light inside, get it out again
+            let m = sample_normal(wo, self.alpha, sampler);
+            let ratio = p.ior;
+            let reflectance = fresnel_dielectric(Vec3::dot(wo, m), ratio);
+            let u = sampler.get_1d();
+            let wi = match refract(-wo, m, ratio).filter(|_| reflectance < u) {
+                Some(refracted) if refracted.z() < 0.0 => refracted,
+                Some(_) => return None,
+                // reflected back inside, has to stay on this side
+                None => match reflect(-wo, m) {
+                    reflected if reflected.z() <= 0.0 => return None,
+                    reflected => reflected,
+                },
+            };
+            return scattered(wi, gray(shadowing(wo, wi, self.alpha)));
+        }
+
+        // clear coat
+        if p.clearcoat > 0.0 {
+            let m = sample_normal(wo, self.clearcoat_alpha, sampler);
+            let reflectance = p.clearcoat * fresnel_dielectric(Vec3::dot(wo, m), 1.0 / 1.5);
+            if sampler.get_1d() < reflectance {
+                let wi = reflect(-wo, m);
+                if wi.z() <= 0.0 {
+                    return None;
+                }
+                return scattered(wi, gray(shadowing(wo, wi, self.clearcoat_alpha)));
+            }
+        }
+
+        let m = sample_normal(wo, self.alpha, sampler);
+        let cos_m = Vec3::dot(wo, m);
+
+        // metal
+        if sampler.get_1d() < p.metallic {
+            let wi = reflect(-wo, m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let f0 = p.base_color;
+            let fresnel = Color::rgb(
+                schlick(f0.r(), cos_m),
+                schlick(f0.g(), cos_m),
+                schlick(f0.b(), cos_m),
+            );
+            return scattered(wi, shadowing(wo, wi, self.alpha) * fresnel);
+        }
+
+        // dielectric specular
+        let f0 = 0.08 * p.specular;
+        if sampler.get_1d() < schlick(f0, cos_m) {
+            let wi = reflect(-wo, m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let tint = mix(white(), self.tint(), p.specular_tint);
+            return scattered(wi, shadowing(wo, wi, self.alpha) * tint);
+        }
+
+        // transmission
+        if sampler.get_1d() < p.transmission {
+            let wi = match refract(-wo, m, 1.0 / p.ior) {
+                Some(refracted) if refracted.z() < 0.0 => refracted,
+                _ => return None,
+            };
+            return scattered(wi, shadowing(wo, wi, self.alpha) * p.base_color);
+        }
+
+        // diffuse and sheen
+        let wi = cosine_hemisphere(sampler.get_2d());
+        let half = (wi + wo).normalized();
+        let sheen = p.sheen * (1.0 - Vec3::dot(wi, half)).max(0.0).powi(5);
+        let sheen_color = mix(white(), self.tint(), p.sheen_tint);
+        scattered(wi, mix(p.base_color, sheen_color, sheen))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        let p = &self.params;
+        let clear = Color::rgb(1.0, 1.0, 1.0);
+        let dielectric = (1.0 - p.transmission) * p.base_color + p.transmission * clear;
+        p.metallic * p.base_color + (1.0 - p.metallic) * dielectric
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<Color> {
+        self.bsdf(rec, wo, wi).map(|(f, _)| f)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.bsdf(rec, wo, wi).map_or(0.0, |(_, pdf)| pdf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn params_are_clamped() {
+        let principled = Principled::new_arc(Params {
+            metallic: 2.0,
+            roughness: -1.0,
+            clearcoat: 1.5,
+            transmission: -0.5,
+            ..Params::default()
+        });
+        let p = &principled.params;
+        assert_eq!(
+            (p.metallic, p.roughness, p.clearcoat, p.transmission),
+            (1.0, 0.0, 1.0, 0.0)
+        );
+    }
+}
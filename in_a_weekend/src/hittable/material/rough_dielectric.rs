@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use super::{
+    dielectric::{absorption_for, beer_lambert, fresnel_dielectric, refract},
+    microfacet::{
+        alpha_from_roughness, g1, g2, reflection, reflection_normal, reflection_pdf,
+        refraction_normal, sample_visible_normal, transmission, transmission_pdf, SMOOTH_ALPHA,
+    },
+    reflect, Material, Scatter,
+};
+use crate::{color::Color, hittable::HitRecord, onb::Onb, ray::Ray, sampler::Sampler, v3::Vec3};
+
+/// Glass with a GGX microfacet surface, like frosted glass or rough
+/// ice.
+///
+/// "Microfacet Models for Refraction through Rough Surfaces" by
+/// Walter et al.  Microfacet normals are sampled from the visible
+/// normals, so reflection and transmission share the same weight.
+pub struct RoughDielectric {
+    refraction_index: f64,
+    alpha: f64,
+    absorption: Color,
+}
+
+impl RoughDielectric {
+    pub fn new_arc(refraction_index: f64, roughness: f64) -> Arc<Self> {
+        Self::absorbing_arc(refraction_index, roughness, Color::rgb(0.0, 0.0, 0.0))
+    }
+
+    /// See `Dielectric::absorbing_arc'.
+    pub fn absorbing_arc(refraction_index: f64, roughness: f64, absorption: Color) -> Arc<Self> {
+        Arc::new(Self {
+            refraction_index,
+            alpha: alpha_from_roughness(roughness).max(SMOOTH_ALPHA),
+            absorption,
+        })
+    }
+
+    /// See `Dielectric::tinted_arc', clamps the same way.
+    pub fn tinted_arc(
+        refraction_index: f64,
+        roughness: f64,
+        transmittance: Color,
+        distance: f64,
+    ) -> Arc<Self> {
+        Self::absorbing_arc(
+            refraction_index,
+            roughness,
+            absorption_for(transmittance, distance),
+        )
+    }
+}
+
+/// `wo' and `wi' in the frame of the surface at `rec', turned so `wo'
+/// is above it, with the indices of refraction on the side of `wo'
+/// and on the other side.  `refraction_index' is the one behind the
+/// front face.
+pub(super) fn dielectric_frame(
+    rec: &HitRecord,
+    wo: Vec3,
+    wi: Vec3,
+    refraction_index: f64,
+) -> (Vec3, Vec3, f64, f64) {
+    let onb = Onb::from_w(rec.normal());
+    let (wo, wi) = (onb.to_local(wo), onb.to_local(wi));
+    let (above, below) = if rec.front_face() {
+        (1.0, refraction_index)
+    } else {
+        (refraction_index, 1.0)
+    };
+    if wo.z() >= 0.0 {
+        (wo, wi, above, below)
+    } else {
+        let flip = |w: Vec3| Vec3::new(w.x(), w.y(), -w.z());
+        (flip(wo), flip(wi), below, above)
+    }
+}
+
+/// BSDF and density of sampling a rough boundary the way
+/// `RoughDielectric' does, from `wo' above it in a medium with index
+/// `eta_o' to `wi', with index `eta_i' below.
+pub(super) fn rough_boundary(wo: Vec3, wi: Vec3, eta_o: f64, eta_i: f64, alpha: f64) -> (f64, f64) {
+    if let Some(m) = reflection_normal(wo, wi) {
+        let reflectance = fresnel_dielectric(Vec3::dot(wo, m), eta_o / eta_i);
+        (
+            reflectance * reflection(wo, wi, m, alpha),
+            reflectance * reflection_pdf(wo, m, alpha),
+        )
+    } else if let Some(m) = refraction_normal(wo, wi, eta_o, eta_i) {
+        let transmitted = 1.0 - fresnel_dielectric(Vec3::dot(wo, m), eta_o / eta_i);
+        (
+            transmitted * transmission(wo, wi, m, eta_o, eta_i, alpha),
+            transmitted * transmission_pdf(wo, wi, m, eta_o, eta_i, alpha),
+        )
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+impl RoughDielectric {
+    /// The absorption depends on the way through the medium, which a
+    /// BSDF doesn't know, so absorbing glass can only be sampled.
+    fn boundary(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<(f64, f64)> {
+        let absorption = self.absorption;
+        if absorption.r() > 0.0 || absorption.g() > 0.0 || absorption.b() > 0.0 {
+            return None;
+        }
+        let (wo, wi, eta_o, eta_i) = dielectric_frame(rec, wo, wi, self.refraction_index);
+        Some(rough_boundary(wo, wi, eta_o, eta_i, self.alpha))
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let refraction_ratio = if rec.front_face() {
+            1.0 / self.refraction_index
+        } else {
+            self.refraction_index
+        };
+
+        let onb = Onb::from_w(rec.normal());
+        let wo = onb.to_local(-ray.dir().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let (u1, u2) = sampler.get_2d();
+        let u = sampler.get_1d();
+        let m = sample_visible_normal(wo, self.alpha, u1, u2);
+        let reflectance = fresnel_dielectric(Vec3::dot(wo, m), refraction_ratio);
+
+        let wi = match refract(-wo, m, refraction_ratio).filter(|_| reflectance < u) {
+            // has to end up below the macro surface
+            Some(refracted) if refracted.z() < 0.0 => refracted,
+            Some(_) => return None,
+            None => {
+                let reflected = reflect(-wo, m);
+                if reflected.z() <= 0.0 {
+                    return None;
+                }
+                reflected
+            }
+        };
+
+        let shadowing = g2(wo, wi, self.alpha) / g1(wo, self.alpha);
+        Some(Scatter {
+            scattered_ray: ray.spawn(rec.point(), onb.to_world(wi)),
+            attenuation: shadowing * beer_lambert(self.absorption, ray, rec),
+            specular: false,
+            in_medium: false,
+        })
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::rgb(1.0, 1.0, 1.0)
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<Color> {
+        let (f, _) = self.boundary(rec, wo, wi)?;
+        Some(Color::rgb(f, f, f))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.boundary(rec, wo, wi).map_or(0.0, |(_, pdf)| pdf)
+    }
+}
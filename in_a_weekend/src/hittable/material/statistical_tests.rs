@@ -0,0 +1,505 @@
+//! Statistical tests of the materials: sampled directions against the
+//! densities they should follow with a chi-square test, white
+//! materials in a furnace, and reciprocity of the BSDFs the materials
+//! evaluate.
+//!
+//! Every material is hit at the origin on a surface facing `z'.
+
+use std::{f64::consts::PI, sync::Arc};
+
+use super::{
+    alpha_mask::AlphaMask,
+    coated::Coated,
+    dielectric::{fresnel_dielectric, reflectance, refract, Dielectric},
+    lambertian::Lambertian,
+    metal::Metal,
+    microfacet::{alpha_from_roughness, directional_albedo, visible_normal_pdf},
+    mix::{Mix, MixWeight},
+    principled::{Params, Principled},
+    rough_dielectric::RoughDielectric,
+    subsurface::Subsurface,
+    Material,
+};
+use crate::{
+    color::Color, hittable::HitRecord, p3::Point3, ray::Ray, sampler::SamplerKind,
+    texture::SolidColor, v3::Vec3,
+};
+
+const SAMPLES: usize = 200_000;
+const COS_BINS: usize = 16;
+const PHI_BINS: usize = 32;
+/// Direction bins and one for absorbed samples.
+const BINS: usize = COS_BINS * PHI_BINS + 1;
+
+fn white() -> Color {
+    Color::rgb(1.0, 1.0, 1.0)
+}
+
+fn direction(cos_theta: f64, phi: f64) -> Vec3 {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Directions `material' is tested with, from grazing to head on.
+fn outgoing() -> [Vec3; 3] {
+    [
+        direction(0.2, 0.4),
+        direction(0.5, 2.0),
+        direction(0.9, -1.0),
+    ]
+}
+
+fn hit(material: &Arc<dyn Material + Send + Sync>, wo: Vec3) -> (Ray, HitRecord) {
+    let ray = Ray::new(Point3::new(wo.x(), wo.y(), wo.z()), -wo, 0.0);
+    let rec = HitRecord::new(&ray, Vec3::new(0.0, 0.0, 1.0), material.clone(), 1.0);
+    (ray, rec)
+}
+
+/// Like `hit', but from inside a closed surface.
+fn hit_inside(material: &Arc<dyn Material + Send + Sync>, wo: Vec3) -> (Ray, HitRecord) {
+    let ray = Ray::new(Point3::new(wo.x(), wo.y(), wo.z()), -wo, 0.0);
+    let rec = HitRecord::new(&ray, Vec3::new(0.0, 0.0, -1.0), material.clone(), 1.0);
+    (ray, rec)
+}
+
+/// Directions and weights of samples of `material' scattering light
+/// from `wo', `None' for absorbed ones.
+fn scatter(material: &Arc<dyn Material + Send + Sync>, wo: Vec3) -> Vec<Option<(Vec3, Color)>> {
+    let (ray, rec) = hit(material, wo);
+    scatter_at(&ray, &rec)
+}
+
+fn scatter_at(ray: &Ray, rec: &HitRecord) -> Vec<Option<(Vec3, Color)>> {
+    let material = rec.material();
+    let mut sampler = SamplerKind::Independent.build(1, 42);
+    (0..SAMPLES)
+        .map(|index| {
+            sampler.start_pixel_sample(0, 0, index);
+            material
+                .scatter(ray, rec, sampler.as_mut())
+                .map(|scatter| (scatter.ray().dir().normalized(), scatter.attenuation()))
+        })
+        .collect()
+}
+
+/// Bins of equal solid angle over the sphere.
+fn bin(direction: Vec3) -> usize {
+    let cos_bin = ((direction.z() + 1.0) / 2.0 * COS_BINS as f64) as usize;
+    let phi = (direction.y().atan2(direction.x()) + PI) / (2.0 * PI);
+    let phi_bin = (phi * PHI_BINS as f64) as usize;
+    cos_bin.min(COS_BINS - 1) * PHI_BINS + phi_bin.min(PHI_BINS - 1)
+}
+
+fn observed(samples: &[Option<(Vec3, Color)>]) -> Vec<f64> {
+    let mut counts = vec![0.0; BINS];
+    for sample in samples {
+        counts[sample.map_or(BINS - 1, |(wi, _)| bin(wi))] += 1.0;
+    }
+    counts
+}
+
+/// Samples expected in the bins from the solid angle density `pdf'
+/// and directions `spikes' picked with the given probabilities.  What
+/// is left is absorbed.
+fn expected(pdf: &dyn Fn(Vec3) -> f64, spikes: &[(Vec3, f64)]) -> Vec<f64> {
+    // midpoint rule, the cosine and the angle keep solid angle
+    let n = 16;
+    let solid_angle = 4.0 * PI / (COS_BINS * PHI_BINS) as f64;
+    let mut probabilities = vec![0.0; BINS];
+    for cos_bin in 0..COS_BINS {
+        for phi_bin in 0..PHI_BINS {
+            let mut sum = 0.0;
+            for i in 0..n * n {
+                let cos = (cos_bin as f64 + ((i % n) as f64 + 0.5) / n as f64) / COS_BINS as f64;
+                let phi = (phi_bin as f64 + ((i / n) as f64 + 0.5) / n as f64) / PHI_BINS as f64;
+                sum += pdf(direction(2.0 * cos - 1.0, 2.0 * PI * phi - PI));
+            }
+            probabilities[cos_bin * PHI_BINS + phi_bin] = sum / (n * n) as f64 * solid_angle;
+        }
+    }
+    for &(spike, probability) in spikes {
+        probabilities[bin(spike)] += probability;
+    }
+    let scattered: f64 = probabilities.iter().sum();
+    probabilities[BINS - 1] = (1.0 - scattered).max(0.0);
+    probabilities.iter().map(|p| p * SAMPLES as f64).collect()
+}
+
+/// Pearson's chi-square test, failing for p-values below about 1e-5.
+/// Bins expected to get few samples are pooled.
+fn assert_chi_square(name: &str, observed: &[f64], expected: &[f64]) {
+    let (mut chi_square, mut cells) = (0.0, 0);
+    let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+    for (&o, &e) in observed.iter().zip(expected) {
+        if e < 5.0 {
+            pooled_observed += o;
+            pooled_expected += e;
+        } else {
+            chi_square += (o - e) * (o - e) / e;
+            cells += 1;
+        }
+    }
+    if pooled_expected > 0.0 {
+        chi_square += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        cells += 1;
+    } else {
+        assert_eq!(pooled_observed, 0.0, "{}: samples where none can be", name);
+    }
+    let freedom = (cells - 1).max(1) as f64;
+
+    // Wilson-Hilferty approximation of the chi-square distribution
+    let variance = 2.0 / (9.0 * freedom);
+    let z = ((chi_square / freedom).cbrt() - (1.0 - variance)) / variance.sqrt();
+    assert!(
+        z < 4.3,
+        "{}: chi-square {} with {} degrees of freedom",
+        name,
+        chi_square,
+        freedom
+    );
+}
+
+fn mirror(wo: Vec3) -> Vec3 {
+    Vec3::new(-wo.x(), -wo.y(), wo.z())
+}
+
+/// Density of reflecting off visible GGX normals.
+fn reflection_pdf(wo: Vec3, wi: Vec3, alpha: f64) -> f64 {
+    if wi.z() <= 0.0 {
+        return 0.0;
+    }
+    let m = (wo + wi).normalized();
+    visible_normal_pdf(wo, m, alpha) / (4.0 * Vec3::dot(wo, m))
+}
+
+/// Density of light from `wo' in a medium with index `eta_o' passing
+/// through visible GGX normals into one with index `eta_i' towards
+/// `wi'.  Both directions in the frame of the side of `wo'.
+fn transmission_pdf(wo: Vec3, wi: Vec3, eta_o: f64, eta_i: f64, alpha: f64) -> f64 {
+    if wi.z() >= 0.0 {
+        return 0.0;
+    }
+    let m = -(wo.scale(eta_o) + wi.scale(eta_i));
+    let m = if m.z() < 0.0 { -m } else { m }.normalized();
+    let (cos_o, cos_i) = (Vec3::dot(wo, m), Vec3::dot(wi, m));
+    if cos_o <= 0.0 || cos_i >= 0.0 {
+        return 0.0;
+    }
+    let jacobian = eta_i * eta_i * cos_i.abs() / (eta_o * cos_o + eta_i * cos_i).powi(2);
+    let transmitted = 1.0 - fresnel_dielectric(cos_o, eta_o / eta_i);
+    visible_normal_pdf(wo, m, alpha) * transmitted * jacobian
+}
+
+#[test]
+pub fn sampling_follows_the_densities() {
+    let lambertian: Arc<dyn Material + Send + Sync> = Lambertian::new_arc(white());
+    let roughness = 0.5;
+    let alpha = alpha_from_roughness(roughness);
+    let metal: Arc<dyn Material + Send + Sync> = Metal::new_arc(white(), roughness);
+    let mix: Arc<dyn Material + Send + Sync> =
+        Mix::new_arc(lambertian.clone(), metal.clone(), MixWeight::Constant(0.3));
+    let rough_glass: Arc<dyn Material + Send + Sync> = RoughDielectric::new_arc(1.5, roughness);
+    let glass: Arc<dyn Material + Send + Sync> = Dielectric::new_arc(1.5);
+    let coated: Arc<dyn Material + Send + Sync> =
+        Coated::new_arc(lambertian.clone(), 1.5, 0.0, white());
+    let skin: Arc<dyn Material + Send + Sync> =
+        Subsurface::new_arc(white(), Color::rgb(0.1, 0.1, 0.1), 1.4);
+
+    // materials built from the ones above, checked against their own
+    // densities
+    let gray = Color::rgb(0.4, 0.4, 0.4);
+    let composed: [(&str, Arc<dyn Material + Send + Sync>); 4] = [
+        (
+            "principled",
+            Principled::new_arc(Params {
+                roughness,
+                clearcoat: 0.5,
+                clearcoat_roughness: 0.4,
+                transmission: 0.5,
+                ..Params::default()
+            }),
+        ),
+        (
+            "alpha mask",
+            AlphaMask::new_arc(metal.clone(), SolidColor::new_arc(gray)),
+        ),
+        (
+            "rough coated",
+            Coated::new_arc(lambertian.clone(), 1.5, 0.4, white()),
+        ),
+        (
+            "textured mix",
+            Mix::new_arc(
+                lambertian.clone(),
+                metal.clone(),
+                MixWeight::Texture(SolidColor::new_arc(gray)),
+            ),
+        ),
+    ];
+
+    for wo in outgoing() {
+        let (_, rec) = hit(&lambertian, wo);
+        let lambertian_pdf = |wi: Vec3| lambertian.pdf(&rec, wo, wi);
+        // single bounces off visible normals, or the diffuse like lobe
+        // of light bouncing more often
+        let single = directional_albedo(wo.z(), alpha);
+        let metal_pdf =
+            |wi: Vec3| single * reflection_pdf(wo, wi, alpha) + (1.0 - single) * lambertian_pdf(wi);
+        let fresnel = |cos: f64| fresnel_dielectric(cos, 1.0 / 1.5);
+        let rough_glass_pdf = |wi: Vec3| {
+            let m = (wo + wi).normalized();
+            let reflected = reflection_pdf(wo, wi, alpha) * fresnel(Vec3::dot(wo, m));
+            reflected + transmission_pdf(wo, wi, 1.0, 1.5, alpha)
+        };
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let refracted = refract(-wo, normal, 1.0 / 1.5).unwrap();
+        let schlick = reflectance(wo.z(), 1.0 / 1.5);
+        let into_skin = refract(-wo, normal, 1.0 / 1.4).unwrap();
+        let skin_fresnel = fresnel_dielectric(wo.z(), 1.0 / 1.4);
+
+        let cases: [(&str, &Arc<dyn Material + Send + Sync>, Vec<f64>); 7] = [
+            ("lambertian", &lambertian, expected(&lambertian_pdf, &[])),
+            ("metal", &metal, expected(&metal_pdf, &[])),
+            (
+                "mix",
+                &mix,
+                expected(&|wi| 0.7 * lambertian_pdf(wi) + 0.3 * metal_pdf(wi), &[]),
+            ),
+            ("rough glass", &rough_glass, expected(&rough_glass_pdf, &[])),
+            (
+                "glass",
+                &glass,
+                expected(
+                    &|_| 0.0,
+                    &[(mirror(wo), schlick), (refracted, 1.0 - schlick)],
+                ),
+            ),
+            (
+                "coated",
+                &coated,
+                expected(
+                    &|wi| (1.0 - fresnel(wo.z())) * lambertian_pdf(wi),
+                    &[(mirror(wo), fresnel(wo.z()))],
+                ),
+            ),
+            (
+                "subsurface",
+                &skin,
+                expected(
+                    &|_| 0.0,
+                    &[(mirror(wo), skin_fresnel), (into_skin, 1.0 - skin_fresnel)],
+                ),
+            ),
+        ];
+        for (name, material, expected) in cases.iter() {
+            let observed = observed(&scatter(material, wo));
+            assert_chi_square(&format!("{} from {:?}", name, wo), &observed, expected);
+        }
+        for (name, material) in composed.iter() {
+            let (_, rec) = hit(material, wo);
+            let expected = expected(&|wi| material.pdf(&rec, wo, wi), &[]);
+            let observed = observed(&scatter(material, wo));
+            assert_chi_square(&format!("{} from {:?}", name, wo), &observed, &expected);
+        }
+    }
+}
+
+#[test]
+pub fn white_materials_keep_all_light() {
+    let mut materials: Vec<(String, Arc<dyn Material + Send + Sync>)> = vec![
+        ("lambertian".to_string(), Lambertian::new_arc(white())),
+        ("glass".to_string(), Dielectric::new_arc(1.5)),
+    ];
+    for roughness in [0.0, 0.3, 0.6, 1.0] {
+        materials.push((
+            format!("metal with roughness {}", roughness),
+            Metal::new_arc(white(), roughness),
+        ));
+    }
+
+    for (name, material) in materials.iter() {
+        for wo in outgoing() {
+            let samples = scatter(material, wo);
+            let albedo = samples
+                .iter()
+                .map(|sample| sample.map_or(0.0, |(_, attenuation)| attenuation.luminance()))
+                .sum::<f64>()
+                / SAMPLES as f64;
+            assert!(
+                (albedo - 1.0).abs() < 0.01,
+                "{} from {:?} keeps {}",
+                name,
+                wo,
+                albedo
+            );
+        }
+    }
+
+    // lobes that lose light at rough surfaces, but may not add any
+    let saturated_red = Color::rgb(0.9, 0.05, 0.05);
+    let principled = [
+        ("white", white(), 0.0),
+        ("white metal", white(), 1.0),
+        ("saturated red", saturated_red, 0.0),
+        ("saturated red metal", saturated_red, 1.0),
+    ];
+    for (name, base_color, metallic) in principled {
+        for roughness in [0.0, 0.5, 1.0] {
+            let material: Arc<dyn Material + Send + Sync> = Principled::new_arc(Params {
+                base_color,
+                metallic,
+                roughness,
+                specular: 1.0,
+                specular_tint: 1.0,
+                sheen: 1.0,
+                sheen_tint: 1.0,
+                clearcoat: 1.0,
+                ..Params::default()
+            });
+            for wo in outgoing() {
+                let samples = scatter(&material, wo);
+                let channel = |channel: fn(&Color) -> f64| {
+                    samples
+                        .iter()
+                        .map(|sample| sample.map_or(0.0, |(_, attenuation)| channel(&attenuation)))
+                        .sum::<f64>()
+                        / SAMPLES as f64
+                };
+                let albedo = [channel(Color::r), channel(Color::g), channel(Color::b)];
+                assert!(
+                    albedo.iter().all(|&albedo| albedo < 1.01),
+                    "principled {} with roughness {} from {:?} keeps {:?}",
+                    name,
+                    roughness,
+                    wo,
+                    albedo
+                );
+            }
+        }
+    }
+}
+
+/// Materials that can be evaluated have to give the same light with
+/// their BSDF over their density as with the weights of their
+/// samples, integrators connecting paths rely on that.
+#[test]
+pub fn samples_match_the_bsdfs() {
+    let lambertian: Arc<dyn Material + Send + Sync> = Lambertian::new_arc(white());
+    let principled = |metallic: f64, transmission: f64| -> Arc<dyn Material + Send + Sync> {
+        Principled::new_arc(Params {
+            base_color: Color::rgb(0.9, 0.5, 0.2),
+            metallic,
+            roughness: 0.5,
+            specular_tint: 0.5,
+            sheen: 0.5,
+            clearcoat: 0.5,
+            clearcoat_roughness: 0.4,
+            transmission,
+            ..Params::default()
+        })
+    };
+    let metal: Arc<dyn Material + Send + Sync> = Metal::new_arc(Color::rgb(0.9, 0.5, 0.2), 0.3);
+    let materials: [(&str, Arc<dyn Material + Send + Sync>); 7] = [
+        ("metal", metal.clone()),
+        ("gold", Metal::gold_arc(0.7)),
+        ("rough glass", RoughDielectric::new_arc(1.5, 0.5)),
+        (
+            "mix",
+            Mix::new_arc(lambertian.clone(), metal, MixWeight::Constant(0.3)),
+        ),
+        ("coated", Coated::new_arc(lambertian, 1.5, 0.4, white())),
+        ("principled", principled(0.0, 0.5)),
+        ("principled metal", principled(0.5, 0.0)),
+    ];
+
+    for (name, material) in materials.iter() {
+        for wo in outgoing() {
+            let sides = [
+                ("outside", hit(material, wo)),
+                ("inside", hit_inside(material, wo)),
+            ];
+            for (side, (ray, rec)) in sides {
+                let wo = -ray.dir().normalized();
+                let mut weights = [0.0; 3];
+                let mut bsdf = [0.0; 3];
+                for (wi, weight) in scatter_at(&ray, &rec).into_iter().flatten() {
+                    let pdf = material.pdf(&rec, wo, wi);
+                    assert!(pdf > 0.0, "{}: sampled {:?} with no density", name, wi);
+                    let f = material.eval(&rec, wo, wi).unwrap();
+                    let f = f.scale(Vec3::dot(wi, rec.normal()).abs() / pdf);
+                    for (sum, value) in weights.iter_mut().zip([weight.r(), weight.g(), weight.b()])
+                    {
+                        *sum += value / SAMPLES as f64;
+                    }
+                    for (sum, value) in bsdf.iter_mut().zip([f.r(), f.g(), f.b()]) {
+                        *sum += value / SAMPLES as f64;
+                    }
+                }
+                for (weight, bsdf) in weights.iter().zip(bsdf) {
+                    assert!(
+                        (weight - bsdf).abs() < 0.01,
+                        "{} from {:?} {}: weights give {:?}, the BSDF {:?}",
+                        name,
+                        wo,
+                        side,
+                        weights,
+                        bsdf
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Light takes the same path both ways.  Coated and principled
+/// materials aren't tested, their layers are mixed by the light
+/// leaving towards `wo' only, like in most renderers.
+#[test]
+pub fn bsdfs_are_reciprocal() {
+    let close = |a: f64, b: f64| (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0);
+    let lambertian: Arc<dyn Material + Send + Sync> = Lambertian::new_arc(white());
+    let metal: Arc<dyn Material + Send + Sync> = Metal::new_arc(white(), 0.5);
+    let reflecting: [(&str, Arc<dyn Material + Send + Sync>); 4] = [
+        ("lambertian", lambertian.clone()),
+        ("metal", metal.clone()),
+        ("gold", Metal::gold_arc(0.7)),
+        (
+            "mix",
+            Mix::new_arc(lambertian, metal, MixWeight::Constant(0.3)),
+        ),
+    ];
+    let rough_glass: Arc<dyn Material + Send + Sync> = RoughDielectric::new_arc(1.5, 0.5);
+
+    let directions: Vec<Vec3> = (0..8)
+        .map(|i| direction(0.1 + 0.11 * i as f64, 0.7 * i as f64))
+        .collect();
+    for &wo in &directions {
+        for &wi in &directions {
+            for (name, material) in reflecting.iter() {
+                let (_, rec) = hit(material, wo);
+                let f = |wo, wi| material.eval(&rec, wo, wi).unwrap();
+                let (there, back) = (f(wo, wi), f(wi, wo));
+                assert!(
+                    close(there.r(), back.r())
+                        && close(there.g(), back.g())
+                        && close(there.b(), back.b()),
+                    "{} between {:?} and {:?}",
+                    name,
+                    wo,
+                    wi
+                );
+            }
+
+            // radiance is scaled by the squared index on the way into
+            // rough glass
+            let (_, rec) = hit(&rough_glass, wo);
+            let f = |wo, wi| rough_glass.eval(&rec, wo, wi).unwrap().r();
+            assert!(close(f(wo, wi), f(wi, wo)));
+            let below = -wi;
+            if wi == wo {
+                assert!(f(wo, below) > 0.0);
+            }
+            assert!(close(f(wo, below) / (1.5 * 1.5), f(below, wo) / 1.0));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use super::{
+    dielectric::{fresnel_dielectric, refract},
+    reflect, Material, Scatter,
+};
+use crate::{
+    color::Color, hittable::HitRecord, ray::Ray, sampler::Sampler, sampling::uniform_sphere,
+    v3::Vec3,
+};
+
+/// Random walk subsurface scattering for skin, wax, marble or milk.
+/// Only works on closed surfaces.
+///
+/// Light gets in through a smooth dielectric boundary and then walks
+/// inside the medium.  Like the absorption of dielectrics, the walk
+/// happens when a ray hits the boundary from the inside: a free
+/// flight distance is sampled and if it's shorter than the segment
+/// the ray scatters in the medium instead of reaching the boundary.
+/// Those scatters are marked as `in_medium', so integrators don't
+/// count them as bounces.
+pub struct Subsurface {
+    /// What the medium looks like after many bounces.
+    color: Color,
+    /// Scattering and extinction coefficients per channel.
+    scattering: Color,
+    extinction: Color,
+    refraction_index: f64,
+}
+
+impl Subsurface {
+    /// `color' is what the medium looks like after many bounces,
+    /// `mean_free_path' is the average distance between scattering
+    /// events for each channel.  Channels shorter than
+    /// `MIN_MEAN_FREE_PATH' are clamped to it.
+    pub fn new_arc(color: Color, mean_free_path: Color, refraction_index: f64) -> Arc<Self> {
+        let coefficient = |path: f64| 1.0 / path.max(MIN_MEAN_FREE_PATH);
+        let extinction = Color::rgb(
+            coefficient(mean_free_path.r()),
+            coefficient(mean_free_path.g()),
+            coefficient(mean_free_path.b()),
+        );
+        let albedo = Color::rgb(
+            single_scattering_albedo(color.r()),
+            single_scattering_albedo(color.g()),
+            single_scattering_albedo(color.b()),
+        );
+        Arc::new(Self {
+            color,
+            scattering: albedo * extinction,
+            extinction,
+            refraction_index,
+        })
+    }
+
+    /// Sample where the walk goes on from along `ray'.  Returns the
+    /// distance to the next scattering event if there is one before
+    /// `length', along with the weight of the sample.
+    ///
+    /// Each channel has its own extinction, a random channel is picked
+    /// for the distance and the channels are combined with the balance
+    /// heuristic.
+    fn free_flight(&self, length: f64, sampler: &mut dyn Sampler) -> (Option<f64>, Color) {
+        let sigma = [
+            self.extinction.r(),
+            self.extinction.g(),
+            self.extinction.b(),
+        ];
+        let (u1, u2) = sampler.get_2d();
+        let channel = ((u1 * 3.0) as usize).min(2);
+        let distance = -(1.0 - u2).ln() / sigma[channel];
+        let transmittance = |d: f64| sigma.map(|s| (-s * d).exp());
+
+        if distance < length {
+            let t = transmittance(distance);
+            let pdf = (0..3).map(|c| sigma[c] * t[c]).sum::<f64>() / 3.0;
+            let weight = Color::rgb(t[0], t[1], t[2]).scale(1.0 / pdf) * self.scattering;
+            (Some(distance), weight)
+        } else {
+            let t = transmittance(length);
+            let pdf = t.iter().sum::<f64>() / 3.0;
+            (None, Color::rgb(t[0], t[1], t[2]).scale(1.0 / pdf))
+        }
+    }
+}
+
+/// Shortest mean free path of a medium, a zero one would make the
+/// extinction infinite.
+pub const MIN_MEAN_FREE_PATH: f64 = 1e-6;
+
+/// Invert the multiple scattering albedo of a medium to the albedo of
+/// a single scattering event.
+///
+/// From "Practical and Controllable Subsurface Scattering for
+/// Production Path Tracing" by Chiang et al.
+fn single_scattering_albedo(color: f64) -> f64 {
+    let a = color.clamp(0.0, 1.0);
+    let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    1.0 - s * s
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let unit_ray_direction = ray.dir().normalized();
+
+        let mut attenuation = Color::rgb(1.0, 1.0, 1.0);
+        if !rec.front_face() {
+            let length = rec.scale() * ray.dir().size();
+            let (distance, weight) = self.free_flight(length, sampler);
+            if let Some(distance) = distance {
+                // scattered inside, isotropically
+                let point = ray.origin() + unit_ray_direction.scale(distance);
+                return Some(Scatter {
+                    scattered_ray: ray.spawn(point, uniform_sphere(sampler.get_2d())),
+                    attenuation: weight,
+                    specular: true,
+                    in_medium: true,
+                });
+            }
+            attenuation = weight;
+        }
+
+        // crossing the boundary
+        let refraction_ratio = if rec.front_face() {
+            1.0 / self.refraction_index
+        } else {
+            self.refraction_index
+        };
+        let cos_theta = Vec3::dot(-unit_ray_direction, rec.normal());
+        let reflectance = fresnel_dielectric(cos_theta, refraction_ratio);
+        let u = sampler.get_1d();
+        let direction = match refract(unit_ray_direction, rec.normal(), refraction_ratio)
+            .filter(|_| reflectance < u)
+        {
+            Some(refracted) => refracted,
+            None => reflect(unit_ray_direction, rec.normal()),
+        };
+        Some(Scatter {
+            scattered_ray: ray.spawn(rec.point(), direction),
+            attenuation,
+            specular: true,
+            in_medium: false,
+        })
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.color
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn mean_free_paths_are_clamped() {
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        let medium = Subsurface::new_arc(white, Color::rgb(0.0, -1.0, f64::NAN), 1.4);
+        let extinction = [
+            medium.extinction.r(),
+            medium.extinction.g(),
+            medium.extinction.b(),
+        ];
+        assert!(extinction.iter().all(|&e| e == 1.0 / MIN_MEAN_FREE_PATH));
+    }
+}
@@ -1,10 +1,13 @@
 use self::material::Material;
-use crate::{Point3, Ray, Vec3};
+use crate::{p3::Point3, ray::Ray, v3::Vec3};
 
 use std::sync::Arc;
 
+pub mod instance;
 pub mod list;
 pub mod material;
+pub mod moving_sphere;
+pub mod quad;
 pub mod sphere;
 
 pub struct HitRecord {
@@ -13,6 +16,10 @@ pub struct HitRecord {
     material: Arc<dyn Material>,
     scale: f64,
     front_face: bool,
+    /// Surface coordinates for textures.
+    uv: (f64, f64),
+    /// Index of the hit object in the list holding it.
+    object_id: usize,
 }
 
 impl std::fmt::Debug for HitRecord {
@@ -23,6 +30,8 @@ impl std::fmt::Debug for HitRecord {
             // .field("material", &self.material)
             .field("scale", &self.scale)
             .field("front_face", &self.front_face)
+            .field("uv", &self.uv)
+            .field("object_id", &self.object_id)
             .finish()
     }
 }
@@ -42,9 +51,28 @@ impl HitRecord {
             material,
             scale,
             front_face,
+            uv: (0.0, 0.0),
+            object_id: 0,
         }
     }
 
+    pub fn with_uv(self, u: f64, v: f64) -> Self {
+        Self { uv: (u, v), ..self }
+    }
+
+    pub fn uv(&self) -> (f64, f64) {
+        self.uv
+    }
+
+    pub fn with_object_id(self, object_id: usize) -> Self {
+        Self { object_id, ..self }
+    }
+
+    /// Lists nested in lists give the index in the outermost one.
+    pub fn object_id(&self) -> usize {
+        self.object_id
+    }
+
     pub fn normal(&self) -> Vec3 {
         self.normal
     }
@@ -53,6 +81,11 @@ impl HitRecord {
         self.point
     }
 
+    /// Ray parameter of the hit.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
     pub fn material(&self) -> Arc<dyn Material> {
         self.material.clone()
     }
@@ -60,8 +93,112 @@ impl HitRecord {
     fn front_face(&self) -> bool {
         self.front_face
     }
+
+    /// Whether `ray' stops at this hit or goes on through a cut out
+    /// part of the surface.  Partial opacity is decided at random,
+    /// with a hash of the hit so the answer is the same for the same
+    /// ray.
+    pub fn is_opaque(&self, ray: &Ray) -> bool {
+        let opacity = self.material.opacity(self);
+        if opacity >= 1.0 {
+            return true;
+        }
+        if opacity <= 0.0 {
+            return false;
+        }
+        hash_to_unit(ray, self.scale) < opacity
+    }
+}
+
+fn hash_to_unit(ray: &Ray, t: f64) -> f64 {
+    let (ox, oy, oz) = ray.origin().xyz();
+    let (dx, dy, dz) = ray.dir().xyz();
+    let mut h: u64 = 0xCBF2_9CE4_8422_2325;
+    for value in [ox, oy, oz, dx, dy, dz, t] {
+        h ^= value.to_bits();
+        h = h.wrapping_mul(0x0000_0100_0000_01B3);
+        h ^= h >> 29;
+    }
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    (h >> 11) as f64 / (1u64 << 53) as f64
 }
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// Every material a hit can have, in a fixed order.
+    fn materials(&self) -> Vec<Arc<dyn Material + Send + Sync>>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::{
+            material::{alpha_mask::AlphaMask, lambertian::Lambertian},
+            quad::Quad,
+            sphere::Sphere,
+        },
+        texture::SolidColor,
+    };
+
+    fn masked(alpha: f64) -> Arc<dyn Material + Send + Sync> {
+        AlphaMask::new_arc(
+            Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5)),
+            SolidColor::new_arc(Color::rgb(alpha, alpha, alpha)),
+        )
+    }
+
+    fn square(material: Arc<dyn Material + Send + Sync>) -> Quad {
+        Quad::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material,
+        )
+    }
+
+    /// Rays towards the square and the sphere at the origin from
+    /// slightly different places.
+    fn rays() -> impl Iterator<Item = Ray> {
+        (0..10_000).map(|i| {
+            let x = (i % 100) as f64 / 100.0 - 0.5;
+            let y = (i / 100) as f64 / 100.0 - 0.5;
+            Ray::new(Point3::new(x, y, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+        })
+    }
+
+    #[test]
+    pub fn transparent_surfaces_let_rays_through() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, masked(0.0));
+        let objects: [&dyn Hittable; 2] = [&sphere, &square(masked(0.0))];
+        for object in objects {
+            assert!(rays().all(|ray| object.hit(&ray, 0.001, f64::INFINITY).is_none()));
+        }
+    }
+
+    #[test]
+    pub fn half_opaque_surfaces_stop_half_of_the_rays() {
+        let quad = square(masked(0.5));
+        let stopped = rays()
+            .filter(|ray| quad.hit(ray, 0.001, f64::INFINITY).is_some())
+            .count();
+        let fraction = stopped as f64 / 10_000.0;
+        assert!((fraction - 0.5).abs() < 0.02, "{}", fraction);
+    }
+
+    #[test]
+    pub fn opacity_is_the_same_for_the_same_ray() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, masked(0.5));
+        let quad = square(masked(0.5));
+        let objects: [&dyn Hittable; 2] = [&sphere, &quad];
+        for object in objects {
+            let hit = |ray: &Ray| object.hit(ray, 0.001, f64::INFINITY).map(|rec| rec.scale());
+            for ray in rays() {
+                assert_eq!(hit(&ray), hit(&ray));
+            }
+        }
+    }
 }
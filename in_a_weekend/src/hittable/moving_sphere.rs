@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use super::{material::Material, sphere::hit_sphere, HitRecord, Hittable};
+use crate::{p3::Point3, ray::Ray};
+
+/// A sphere moving linearly from `center0` at `time0` to `center1`
+/// at `time1`.
+pub struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Arc<dyn Material + Send + Sync>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        (center0, time0): (Point3, f64),
+        (center1, time1): (Point3, f64),
+        radius: f64,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn new_arc(
+        start: (Point3, f64),
+        end: (Point3, f64),
+        radius: f64,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self::new(start, end, radius, material))
+    }
+
+    fn center(&self, time: f64) -> Point3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let t = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + (self.center1 - self.center0).scale(t)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let center = self.center(ray.time());
+        hit_sphere(center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material + Send + Sync>> {
+        vec![self.material.clone()]
+    }
+}
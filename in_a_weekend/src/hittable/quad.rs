@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use super::{material::Material, HitRecord, Hittable};
+use crate::{p3::Point3, ray::Ray, stats, v3::Vec3};
+
+/// Parallelogram with a corner at `corner' and sides `u' and `v'.
+/// Texture coordinates go from 0 to 1 along the sides.
+pub struct Quad {
+    corner: Point3,
+    u: Vec3,
+    v: Vec3,
+    material: Arc<dyn Material + Send + Sync>,
+    normal: Vec3,
+    // plane is `dot(normal, p) = d'
+    d: f64,
+    // for finding the planar coordinates of a point
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(
+        corner: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        let n = Vec3::cross(u, v);
+        let normal = n.normalized();
+        let d = Vec3::dot(normal, corner.into());
+        let w = n.scale(1.0 / n.length_squared());
+        Self {
+            corner,
+            u,
+            v,
+            material,
+            normal,
+            d,
+            w,
+        }
+    }
+
+    pub fn new_arc(
+        corner: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self::new(corner, u, v, material))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        stats::intersection_test();
+        let denominator = Vec3::dot(self.normal, ray.dir());
+        // parallel to the plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - Vec3::dot(self.normal, ray.origin().into())) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let planar = ray.at(t) - self.corner;
+        let alpha = Vec3::dot(self.w, Vec3::cross(planar, self.v));
+        let beta = Vec3::dot(self.w, Vec3::cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let rec = HitRecord::new(ray, self.normal, self.material.clone(), t).with_uv(alpha, beta);
+        Some(rec).filter(|rec| rec.is_opaque(ray))
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material + Send + Sync>> {
+        vec![self.material.clone()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{color::Color, hittable::material::lambertian::Lambertian};
+
+    #[test]
+    pub fn hits_between_the_sides() {
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5)),
+        );
+        let towards = |x: f64, y: f64| {
+            let ray = Ray::new(Point3::new(x, y, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            quad.hit(&ray, 0.001, f64::INFINITY)
+        };
+
+        let rec = towards(0.5, 0.0).unwrap();
+        assert!((rec.scale() - 2.0).abs() < 1e-12);
+        let (u, v) = rec.uv();
+        assert!((u - 0.75).abs() < 1e-12 && (v - 0.25).abs() < 1e-12);
+        assert!(towards(1.5, 0.0).is_none());
+        assert!(towards(0.0, -1.5).is_none());
+        assert!(towards(0.0, 3.5).is_none());
+    }
+}
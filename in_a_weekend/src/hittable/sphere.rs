@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use super::{material::Material, HitRecord, Hittable};
-use crate::{p3::Point3, ray::Ray, v3::Vec3};
+use crate::{p3::Point3, ray::Ray, sampling::uniform_sphere, stats, v3::Vec3};
 
 pub struct Sphere {
     center: Point3,
@@ -21,39 +21,79 @@ impl Sphere {
     pub fn new_arc(center: Point3, radius: f64, material: Arc<dyn Material + Send + Sync>) -> Arc<Self> {
         Arc::new(Self::new(center, radius, material))
     }
+
+    pub fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    /// Uniformly distributed point on the surface and the outward
+    /// normal there, for `u1' and `u2' uniform in `[0, 1)'.
+    pub fn sample_surface(&self, u1: f64, u2: f64) -> (Point3, Vec3) {
+        let normal = uniform_sphere((u1, u2));
+        (self.center + normal.scale(self.radius), normal)
+    }
+
+    /// Whether `point' is on the surface, up to rounding errors.
+    pub fn is_on_surface(&self, point: Point3) -> bool {
+        ((point - self.center).size() - self.radius).abs() < 1e-6 * self.radius.max(1.0)
+    }
+
+    pub fn material(&self) -> Arc<dyn Material + Send + Sync> {
+        self.material.clone()
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc: Vec3 = ray.origin() - self.center;
-        // variables for quadratic equation
-        let a = Vec3::dot(ray.dir(), ray.dir());
-        let half_b = Vec3::dot(oc, ray.dir());
-        let c = Vec3::dot(oc, oc) - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-
-        // ray intersects sphere
-        if discriminant < 0.0 {
-            return None;
-        }
+        hit_sphere(self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
 
-        let sqrt_d = discriminant.sqrt();
+    fn materials(&self) -> Vec<Arc<dyn Material + Send + Sync>> {
+        vec![self.material.clone()]
+    }
+}
 
-        // find nearest root in acceptable range
-        let mut root = (-half_b - sqrt_d) / a;
-        if root < t_min || root > t_max {
-            // check other root
-            root = (-half_b + sqrt_d) / a;
-        }
-        if root < t_min || root > t_max {
-            // none of the roots within range
-            return None;
-        }
+pub(super) fn hit_sphere(
+    center: Point3,
+    radius: f64,
+    material: &Arc<dyn Material + Send + Sync>,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord> {
+    stats::intersection_test();
+    let oc: Vec3 = ray.origin() - center;
+    // variables for quadratic equation
+    let a = Vec3::dot(ray.dir(), ray.dir());
+    let half_b = Vec3::dot(oc, ray.dir());
+    let c = Vec3::dot(oc, oc) - radius * radius;
+    let discriminant = half_b * half_b - a * c;
 
-        let normal = (ray.at(root) - self.center).scale(1.0 / self.radius);
+    // ray intersects sphere
+    if discriminant < 0.0 {
+        return None;
+    }
 
-        let rec = HitRecord::new(ray, normal, self.material.clone(), root);
+    let sqrt_d = discriminant.sqrt();
 
-        Some(rec)
-    }
+    // nearest root in acceptable range that isn't cut out
+    [(-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a]
+        .iter()
+        .copied()
+        .filter(|root| *root >= t_min && *root <= t_max)
+        .map(|root| {
+            let normal = (ray.at(root) - center).scale(1.0 / radius);
+            let (u, v) = sphere_uv(normal);
+            HitRecord::new(ray, normal, material.clone(), root).with_uv(u, v)
+        })
+        .find(|rec| rec.is_opaque(ray))
+}
+
+/// Texture coordinates for a point on the unit sphere.  `u' goes
+/// around the `y' axis starting from `-x', `v' goes from the bottom
+/// to the top.
+fn sphere_uv(p: Vec3) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = f64::atan2(-p.z(), p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
 }
@@ -0,0 +1,100 @@
+use super::{path, T_MIN};
+use crate::{
+    color::Color,
+    film::{id_color, Aov},
+    ray::Ray,
+    sampler::Sampler,
+    scene::Scene,
+};
+
+/// Values of `aovs' for a camera ray.  `light' is the direct and
+/// indirect light along it, if already known.
+pub fn evaluate(
+    ray: &Ray,
+    scene: &Scene,
+    depth: usize,
+    aovs: &[Aov],
+    light: Option<(Color, Color)>,
+    sampler: &mut dyn Sampler,
+) -> Vec<Color> {
+    let black = Color::rgb(0.0, 0.0, 0.0);
+    let needs_light = aovs
+        .iter()
+        .any(|aov| matches!(aov, Aov::Direct | Aov::Indirect));
+    let (direct, indirect) = match light {
+        Some(light) => light,
+        None if needs_light => path::trace(ray, scene, depth, sampler),
+        None => (black, black),
+    };
+
+    let rec = scene.hit(ray, T_MIN, f64::INFINITY);
+    aovs.iter()
+        .map(|aov| match (aov, &rec) {
+            (Aov::Direct, _) => direct,
+            (Aov::Indirect, _) => indirect,
+            (_, None) => black,
+            (Aov::Albedo, Some(rec)) => rec.material().albedo(rec),
+            (Aov::Normal, Some(rec)) => Color::from_normal(rec.normal()),
+            (Aov::Depth, Some(rec)) => {
+                let depth = rec.scale() * ray.dir().size();
+                Color::rgb(depth, depth, depth)
+            }
+            (Aov::Position, Some(rec)) => {
+                let (x, y, z) = rec.point().xyz();
+                Color::rgb(x, y, z)
+            }
+            (Aov::MaterialId, Some(rec)) => scene
+                .material_id(&rec.material())
+                .map_or(black, |id| id_color(id as u64)),
+            (Aov::ObjectId, Some(rec)) => id_color(rec.object_id() as u64),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hittable::{
+            material::{
+                lambertian::Lambertian,
+                metal::Metal,
+                mix::{Mix, MixWeight},
+            },
+            sphere::Sphere,
+        },
+        p3::Point3,
+        sampler::SamplerKind,
+    };
+
+    #[test]
+    pub fn albedo_is_the_same_for_every_sample() {
+        let red = Color::rgb(0.8, 0.1, 0.1);
+        let gold = Color::rgb(1.0, 0.8, 0.3);
+        let mut scene = Scene::new();
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Mix::new_arc(
+                Lambertian::new_arc(red),
+                Metal::new_arc(gold, 0.0),
+                MixWeight::Constant(0.25),
+            ),
+        ));
+        let origin = Point3::new(0.0, 0.0, 3.0);
+        let ray = Ray::new(origin, Point3::new(0.0, 0.0, 0.0) - origin, 0.0);
+        let expected = 0.75 * red + 0.25 * gold;
+
+        let mut sampler = SamplerKind::Independent.build(16, 0);
+        for sample in 0..16 {
+            sampler.start_pixel_sample(0, 0, sample);
+            let albedo = evaluate(&ray, &scene, 8, &[Aov::Albedo], None, sampler.as_mut())[0];
+            let channels = [
+                albedo.r() - expected.r(),
+                albedo.g() - expected.g(),
+                albedo.b() - expected.b(),
+            ];
+            assert!(channels.iter().all(|c| c.abs() < 1e-9), "{:?}", albedo);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+//! Bidirectional path tracing after "Robust Monte Carlo Methods for
+//! Light Transport Simulation" by Veach, structured like the one in
+//! pbrt.
+//!
+//! A path from the camera and a path from a light are traced, then
+//! every prefix of one is connected to every prefix of the other.  The
+//! strategies are combined with the balance heuristic.  Connecting
+//! light paths straight to the camera (t = 1) would need splatting to
+//! other pixels, so that strategy isn't used.  The sky can't be
+//! sampled from the light side, its light is only found by the camera
+//! path.
+
+use std::f64::consts::PI;
+
+use super::{background, emission_at, pick_light, MAX_MEDIUM_STEPS, T_MIN};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    onb::Onb,
+    p3::Point3,
+    ray::Ray,
+    sampler::Sampler,
+    sampling::{cosine_hemisphere, cosine_hemisphere_pdf},
+    scene::Scene,
+    stats::{self, PathEnd},
+    v3::Vec3,
+};
+
+enum Kind {
+    Camera,
+    /// Start of a light path.
+    Light {
+        emission: Color,
+    },
+    Surface {
+        rec: HitRecord,
+        wo: Vec3,
+    },
+}
+
+struct Vertex {
+    kind: Kind,
+    point: Point3,
+    /// Zero for the camera.
+    normal: Vec3,
+    /// Throughput of the subpath up to this vertex.
+    beta: Color,
+    /// Area density of sampling this vertex from the previous vertex
+    /// of its subpath.
+    pdf_fwd: f64,
+    /// Area density of sampling this vertex from the next one, going
+    /// the other way.
+    pdf_rev: f64,
+    /// Scattered specularly, the next vertex has no density.
+    delta: bool,
+    /// Has a BSDF to connect to, also where it was sampled specularly
+    /// in a mix with a specular material.
+    connectible: bool,
+}
+
+fn black() -> Color {
+    Color::rgb(0.0, 0.0, 0.0)
+}
+
+fn is_black(c: Color) -> bool {
+    c.r() == 0.0 && c.g() == 0.0 && c.b() == 0.0
+}
+
+impl Vertex {
+    fn camera(point: Point3) -> Self {
+        Self {
+            kind: Kind::Camera,
+            point,
+            normal: Vec3::zero(),
+            beta: Color::rgb(1.0, 1.0, 1.0),
+            pdf_fwd: 1.0,
+            pdf_rev: 0.0,
+            delta: false,
+            connectible: true,
+        }
+    }
+
+    /// Turn a solid angle density for the direction from `self' to
+    /// `next' into an area density at `next'.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let d = next.point - self.point;
+        let dist_squared = d.length_squared();
+        if dist_squared == 0.0 {
+            return 0.0;
+        }
+        let cos = if next.normal.near_zero() {
+            1.0
+        } else {
+            Vec3::dot(next.normal, d).abs() / dist_squared.sqrt()
+        };
+        pdf * cos / dist_squared
+    }
+
+    /// What goes from `self' towards `next': the BSDF on surfaces or
+    /// the emission on lights.
+    fn f(&self, next: &Vertex) -> Color {
+        let wi = (next.point - self.point).normalized();
+        match &self.kind {
+            Kind::Camera => black(),
+            Kind::Light { emission } => {
+                if Vec3::dot(self.normal, wi) > 0.0 {
+                    *emission
+                } else {
+                    black()
+                }
+            }
+            Kind::Surface { rec, wo } => rec.material().eval(rec, *wo, wi).unwrap_or_else(black),
+        }
+    }
+
+    /// Area density at `next' of sampling it from `self', having come
+    /// from `prev'.
+    fn pdf(&self, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let wi = (next.point - self.point).normalized();
+        let pdf = match &self.kind {
+            Kind::Camera => 0.0,
+            Kind::Light { .. } => Vec3::dot(self.normal, wi).max(0.0) / PI,
+            Kind::Surface { rec, wo } => {
+                let wo = prev.map_or(*wo, |prev| (prev.point - self.point).normalized());
+                rec.material().pdf(rec, wo, wi)
+            }
+        };
+        self.convert_density(pdf, next)
+    }
+
+    /// Area density at `next' of emitting towards it from `self',
+    /// which has to be on a light.
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let wi = (next.point - self.point).normalized();
+        self.convert_density(Vec3::dot(self.normal, wi).max(0.0) / PI, next)
+    }
+
+    fn emitted(&self) -> Color {
+        match &self.kind {
+            Kind::Surface { rec, .. } => rec.material().emitted(rec),
+            _ => black(),
+        }
+    }
+}
+
+/// Area density of light paths starting at `point', `None' if it's
+/// not on one of the lights of the scene.
+fn pdf_light_origin(scene: &Scene, point: Point3) -> Option<f64> {
+    let lights = scene.lights();
+    lights
+        .iter()
+        .find(|light| light.is_on_surface(point))
+        .map(|light| 1.0 / (lights.len() as f64 * light.area()))
+}
+
+pub fn ray_color(ray: &Ray, scene: &Scene, depth: usize, sampler: &mut dyn Sampler) -> Color {
+    let mut camera_path = vec![Vertex::camera(ray.origin())];
+    let mut color = random_walk(
+        scene,
+        ray.spawn(ray.origin(), ray.dir()),
+        Color::rgb(1.0, 1.0, 1.0),
+        1.0,
+        depth,
+        &mut camera_path,
+        sampler,
+    );
+
+    let light_path = light_subpath(scene, ray.time(), depth, sampler);
+
+    for t in 2..=camera_path.len() {
+        for s in 0..=light_path.len() {
+            // number of segments is limited like for the camera path
+            if s + t - 1 > depth {
+                break;
+            }
+            let contribution = connect(scene, &light_path, &camera_path, s, t, ray.time());
+            if !is_black(contribution) {
+                color += mis_weight(scene, &light_path, &camera_path, s, t) * contribution;
+            }
+        }
+    }
+    color
+}
+
+fn light_subpath(scene: &Scene, time: f64, depth: usize, sampler: &mut dyn Sampler) -> Vec<Vertex> {
+    let lights = scene.lights();
+    if lights.is_empty() || depth == 0 {
+        return Vec::new();
+    }
+    let light = pick_light(lights, sampler.get_1d());
+    let (u1, u2) = sampler.get_2d();
+    let (point, normal) = light.sample_surface(u1, u2);
+    let pdf_pos = 1.0 / (lights.len() as f64 * light.area());
+    let emission = emission_at(light, point, normal, time);
+
+    // cosine weighted direction
+    let local = cosine_hemisphere(sampler.get_2d());
+    let dir = Onb::from_w(normal).to_world(local);
+    let pdf_dir = cosine_hemisphere_pdf(local);
+
+    let mut path = vec![Vertex {
+        kind: Kind::Light { emission },
+        point,
+        normal,
+        beta: Color::rgb(1.0, 1.0, 1.0).scale(1.0 / pdf_pos),
+        pdf_fwd: pdf_pos,
+        pdf_rev: 0.0,
+        delta: false,
+        connectible: true,
+    }];
+    if pdf_dir > 0.0 {
+        let beta = emission.scale(local.z() / (pdf_pos * pdf_dir));
+        let ray = Ray::new(point, dir, time);
+        random_walk(scene, ray, beta, pdf_dir, depth - 1, &mut path, sampler);
+    }
+    path
+}
+
+/// Extend `path' with up to `max_vertices' vertices.  Returns the
+/// light from the sky if a path from the camera escapes.
+///
+/// Steps through a medium don't become vertices, they can't be
+/// connected to.  The walk is folded into the throughput between the
+/// surfaces where it went in and came out, like a chain of specular
+/// bounces.
+fn random_walk(
+    scene: &Scene,
+    mut ray: Ray,
+    mut beta: Color,
+    mut pdf_dir: f64,
+    max_vertices: usize,
+    path: &mut Vec<Vertex>,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let from_camera = matches!(path.first().map(|vertex| &vertex.kind), Some(Kind::Camera));
+    // only paths from the camera are counted
+    let ended = |vertices: usize, end: PathEnd| {
+        if from_camera {
+            stats::path_ended(vertices, end)
+        }
+    };
+    let mut bounce = 0;
+    let mut medium_steps = 0;
+    while bounce < max_vertices {
+        let rec = match scene.hit(&ray, T_MIN, f64::INFINITY) {
+            Some(rec) => rec,
+            None if from_camera => {
+                ended(bounce, PathEnd::Escaped);
+                return beta * background(scene, &ray);
+            }
+            None => return black(),
+        };
+        let material = rec.material();
+        let wo = -ray.dir().normalized();
+        let connectible = material.eval(&rec, wo, wo).is_some();
+        let scatter = material.scatter(&ray, &rec, sampler);
+
+        if let Some(scatter) = scatter.as_ref().filter(|scatter| scatter.in_medium()) {
+            medium_steps += 1;
+            if medium_steps == MAX_MEDIUM_STEPS {
+                ended(bounce + 1, PathEnd::DepthLimit);
+                return black();
+            }
+            beta = beta * scatter.attenuation();
+            pdf_dir = 0.0;
+            let next = scatter.ray();
+            ray = next.spawn(next.origin(), next.dir());
+            continue;
+        }
+
+        let prev = path
+            .last()
+            .expect("Subpaths start at the camera or a light");
+        let mut vertex = Vertex {
+            point: rec.point(),
+            normal: rec.normal(),
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            // a mix is only specular where that part was sampled
+            delta: scatter
+                .as_ref()
+                .map_or(!connectible, |scatter| scatter.is_specular()),
+            connectible,
+            kind: Kind::Surface { rec, wo },
+        };
+        vertex.pdf_fwd = prev.convert_density(pdf_dir, &vertex);
+
+        let scatter = match scatter {
+            Some(scatter) => scatter,
+            None => {
+                path.push(vertex);
+                ended(bounce + 1, PathEnd::Absorbed);
+                return black();
+            }
+        };
+
+        let wi = scatter.ray().dir().normalized();
+        let pdf_rev = if let Kind::Surface { rec, .. } = &vertex.kind {
+            if vertex.delta {
+                pdf_dir = 0.0;
+                0.0
+            } else {
+                pdf_dir = material.pdf(rec, wo, wi);
+                material.pdf(rec, wi, wo)
+            }
+        } else {
+            unreachable!()
+        };
+        beta = beta * scatter.attenuation();
+
+        let prev_pdf_rev = vertex.convert_density(pdf_rev, prev);
+        let prev_index = path.len() - 1;
+        path[prev_index].pdf_rev = prev_pdf_rev;
+        path.push(vertex);
+
+        let next = scatter.ray();
+        ray = next.spawn(next.origin(), next.dir());
+        bounce += 1;
+    }
+    ended(max_vertices, PathEnd::DepthLimit);
+    black()
+}
+
+/// Unweighted contribution of connecting the first `s' vertices of
+/// the light path with the first `t' of the camera path.
+fn connect(
+    scene: &Scene,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+    time: f64,
+) -> Color {
+    let pt = &camera_path[t - 1];
+    if s == 0 {
+        // the camera path found a light by itself
+        return pt.beta * pt.emitted();
+    }
+
+    let qs = &light_path[s - 1];
+    if !pt.connectible || !qs.connectible {
+        return black();
+    }
+    let d = qs.point - pt.point;
+    let dist_squared = d.length_squared();
+    let dir = d.normalized();
+    let cos_pt = Vec3::dot(pt.normal, dir).abs();
+    let cos_qs = if qs.normal.near_zero() {
+        1.0
+    } else {
+        Vec3::dot(qs.normal, dir).abs()
+    };
+    let g = cos_pt * cos_qs / dist_squared;
+
+    let contribution = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta;
+    if is_black(contribution) {
+        return black();
+    }
+    let shadow_ray = Ray::new(pt.point, d, time);
+    if scene.hit(&shadow_ray, T_MIN, 1.0 - 1e-4).is_some() {
+        return black();
+    }
+    contribution.scale(g)
+}
+
+struct Densities {
+    fwd: f64,
+    rev: f64,
+    delta: bool,
+}
+
+fn densities(path: &[Vertex], len: usize) -> Vec<Densities> {
+    path[..len]
+        .iter()
+        .map(|v| Densities {
+            fwd: v.pdf_fwd,
+            rev: v.pdf_rev,
+            delta: v.delta,
+        })
+        .collect()
+}
+
+/// Balance heuristic weight of the `(s, t)' strategy.
+fn mis_weight(
+    scene: &Scene,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+) -> f64 {
+    let pt = &camera_path[t - 1];
+    let pt_minus = &camera_path[t - 2];
+    let qs = s.checked_sub(1).map(|i| &light_path[i]);
+    let qs_minus = s.checked_sub(2).map(|i| &light_path[i]);
+
+    // densities as they are along the connected path
+    let mut camera = densities(camera_path, t);
+    let mut light = densities(light_path, s);
+    camera[t - 1].delta = false;
+    match qs {
+        Some(qs) => {
+            light[s - 1].delta = false;
+            camera[t - 1].rev = qs.pdf(qs_minus, pt);
+            camera[t - 2].rev = pt.pdf(Some(qs), pt_minus);
+            light[s - 1].rev = pt.pdf(Some(pt_minus), qs);
+            if let Some(qs_minus) = qs_minus {
+                light[s - 2].rev = qs.pdf(Some(pt), qs_minus);
+            }
+        }
+        None => match pdf_light_origin(scene, pt.point) {
+            Some(pdf) => {
+                camera[t - 1].rev = pdf;
+                camera[t - 2].rev = pt.pdf_light(pt_minus);
+            }
+            // not a light that can be sampled, this is the only way
+            None => return 1.0,
+        },
+    }
+
+    // deltas have no density, they cancel out in the ratios
+    let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+    let mut sum = 0.0;
+
+    let mut ratio = 1.0;
+    for i in (2..t).rev() {
+        ratio *= remap(camera[i].rev) / remap(camera[i].fwd);
+        if !camera[i].delta && !camera[i - 1].delta {
+            sum += ratio;
+        }
+    }
+
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light[i].rev) / remap(light[i].fwd);
+        let delta_before = i > 0 && light[i - 1].delta;
+        if !light[i].delta && !delta_before {
+            sum += ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
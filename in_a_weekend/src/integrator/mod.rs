@@ -0,0 +1,354 @@
+use std::sync::Arc;
+
+use crate::{
+    color::Color,
+    film::Aov,
+    hittable::{sphere::Sphere, HitRecord},
+    p3::Point3,
+    ray::Ray,
+    sampler::Sampler,
+    scene::Scene,
+    v3::Vec3,
+};
+
+mod aov;
+mod bdpt;
+mod path;
+mod photon;
+mod spectral;
+
+/// How the light arriving along a camera ray is estimated.
+#[derive(Clone, Copy, Debug)]
+pub enum Integrator {
+    /// Unidirectional path tracing in RGB.
+    Path,
+    /// Path tracing with hero wavelength sampling.  Needed for
+    /// dispersion.
+    Spectral,
+    /// Bidirectional path tracing, connecting paths from the camera
+    /// with paths from the lights of the scene.
+    Bidirectional,
+    /// Path tracing with caustics looked up in a photon map instead.
+    Photon,
+}
+
+impl Integrator {
+    /// Light along `ray', with the numbers of the path taken from
+    /// `sampler'.
+    pub fn ray_color(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        depth: usize,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        match self {
+            Integrator::Path => path::ray_color(ray, scene, depth, sampler),
+            Integrator::Spectral => spectral::ray_color(ray, scene, depth, sampler),
+            Integrator::Bidirectional => bdpt::ray_color(ray, scene, depth, sampler),
+            Integrator::Photon => photon::ray_color(ray, scene, depth, sampler),
+        }
+    }
+
+    /// Light along `ray' and the values of `aovs' for it.
+    pub fn sample(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        depth: usize,
+        aovs: &[Aov],
+        sampler: &mut dyn Sampler,
+    ) -> (Color, Vec<Color>) {
+        if aovs.is_empty() {
+            return (self.ray_color(ray, scene, depth, sampler), Vec::new());
+        }
+        // the path tracer splits its light itself, others are traced
+        // again for the split
+        match self {
+            Integrator::Path => {
+                let light = path::trace(ray, scene, depth, sampler);
+                let aovs = aov::evaluate(ray, scene, depth, aovs, Some(light), sampler);
+                (light.0 + light.1, aovs)
+            }
+            _ => {
+                let color = self.ray_color(ray, scene, depth, sampler);
+                (color, aov::evaluate(ray, scene, depth, aovs, None, sampler))
+            }
+        }
+    }
+
+    /// Work done once for every frame before the rays of the camera
+    /// are traced, with the shutter open from `open' to `close'.  The
+    /// photon map gets `photons' shot into it, the ones within
+    /// `photon_radius' of a hit light it.
+    pub fn prepare(
+        &self,
+        scene: &mut Scene,
+        (open, close): (f64, f64),
+        photons: usize,
+        photon_radius: f64,
+    ) {
+        if let Integrator::Photon = self {
+            let caustics = photon::trace_caustics(scene, open, close, photons);
+            scene.set_caustics(caustics, photon_radius);
+        }
+    }
+}
+
+/// Closest hit accepted, to avoid self bounces.
+const T_MIN: f64 = 0.001;
+
+/// Bounces a path always takes before Russian roulette can end it.
+const MIN_BOUNCES: usize = 3;
+
+/// Steps of random walks through media a path can take on top of its
+/// bounces.  A dense medium needs many steps to get through, they
+/// don't count as bounces, so they neither use up the depth nor get
+/// ended by Russian roulette.
+const MAX_MEDIUM_STEPS: usize = 4096;
+
+/// Randomly end paths that carry little light after `bounce', where
+/// `throughput' is the largest component of what they carry.  Returns
+/// what surviving paths have to be scaled by to keep the expected
+/// value, `None' if the path ends.  `u' is uniform in `[0, 1)'.
+fn russian_roulette(bounce: usize, throughput: f64, u: f64) -> Option<f64> {
+    if bounce < MIN_BOUNCES {
+        return Some(1.0);
+    }
+    let survival = throughput.min(0.95);
+    if survival <= 0.0 || u >= survival {
+        return None;
+    }
+    Some(1.0 / survival)
+}
+
+/// Light arriving along a ray that escaped the world.
+fn background(scene: &Scene, ray: &Ray) -> Color {
+    if let Some(environment) = scene.environment() {
+        return environment;
+    }
+    let dir = ray.dir().normalized();
+    let t = 0.5 * (dir.y() + 1.0);
+    let mix_factor_sky_bottom = 1.0 - t;
+    let mix_factor_sky_top = t;
+    let sky_bottom_color = Color::rgb(1.0, 1.0, 1.0).into_gamma();
+    let sky_top_color = Color::rgb(0.5, 0.7, 1.0).into_gamma();
+    let sky_color = mix_factor_sky_bottom * sky_bottom_color + mix_factor_sky_top * sky_top_color;
+    let scaled_sky = sky_color.scale(1.0/16.0);
+    let sun_color = Color::rgb(1.0, 1.0, 0.9);
+    let mix_factor_sun = Vec3::dot(sun_dir(), dir).max(0.0).powi(SUN_EXPONENT);
+    sun_color.scale(mix_factor_sun) + scaled_sky
+}
+
+/// The sun in the sky falls off with this power of the cosine.
+const SUN_EXPONENT: i32 = 8;
+
+fn sun_dir() -> Vec3 {
+    Vec3::new(1.0, 1.0, -1.0).normalized()
+}
+
+/// One of `lights' picked uniformly with `u' in `[0, 1)'.
+fn pick_light(lights: &[Arc<Sphere>], u: f64) -> &Sphere {
+    &lights[((u * lights.len() as f64) as usize).min(lights.len() - 1)]
+}
+
+/// Light given off by `light' at a `point' on it with `normal'.
+fn emission_at(light: &Sphere, point: Point3, normal: Vec3, time: f64) -> Color {
+    // pretend to be looking at the light from outside
+    let ray = Ray::new(point + normal, -normal, time);
+    let rec = HitRecord::new(&ray, normal, light.material(), 1.0);
+    light.material().emitted(&rec)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hittable::material::{
+            dielectric::Dielectric,
+            diffuse_light::DiffuseLight,
+            lambertian::Lambertian,
+            metal::Metal,
+            mix::{Mix, MixWeight},
+            subsurface::Subsurface,
+            Material,
+        },
+        sampler::SamplerKind,
+    };
+
+    #[test]
+    pub fn russian_roulette_keeps_the_expected_value() {
+        let runs = 100_000;
+        let sum: f64 = (0..runs)
+            .map(|run| {
+                let u = (run as f64 + 0.5) / runs as f64;
+                russian_roulette(MIN_BOUNCES, 0.3, u).unwrap_or(0.0)
+            })
+            .sum();
+        assert!((sum / runs as f64 - 1.0).abs() < 0.05);
+        assert_eq!(russian_roulette(0, 0.0, 0.5), Some(1.0));
+    }
+
+    #[test]
+    pub fn white_furnace() {
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        let materials: [(&str, Arc<dyn Material + Send + Sync>); 4] = [
+            ("lambertian", Lambertian::new_arc(white)),
+            ("glass", Dielectric::new_arc(1.5)),
+            ("metal", Metal::new_arc(white, 0.5)),
+            // walks of about a hundred steps
+            (
+                "subsurface",
+                Subsurface::new_arc(white, Color::rgb(0.1, 0.1, 0.1), 1.4),
+            ),
+        ];
+        let integrators = [
+            Integrator::Path,
+            Integrator::Spectral,
+            Integrator::Bidirectional,
+            Integrator::Photon,
+        ];
+        let runs = 20_000;
+        for (name, material) in materials.iter() {
+            let mut scene = Scene::new();
+            scene.add(Sphere::new_arc(
+                Point3::new(0.0, 0.0, 0.0),
+                1.0,
+                material.clone(),
+            ));
+            scene.set_environment(white);
+
+            for integrator in integrators.iter() {
+                integrator.prepare(&mut scene, (0.0, 1.0), 20_000, 0.05);
+                let mut sampler = SamplerKind::Sobol.build(runs, 0);
+                let sum = (0..runs)
+                    .map(|run| {
+                        sampler.start_pixel_sample(0, 0, run);
+                        // rays spread over the sphere as seen from
+                        // the front
+                        let (x, y) = sampler.get_2d();
+                        let target = Point3::new(1.4 * x - 0.7, 1.4 * y - 0.7, 0.0);
+                        let origin = Point3::new(0.0, 0.0, 3.0);
+                        let ray = Ray::new(origin, target - origin, 0.0);
+                        integrator
+                            .ray_color(&ray, &scene, 64, sampler.as_mut())
+                            .luminance()
+                    })
+                    .sum::<f64>();
+                let mean = sum / runs as f64;
+                assert!(
+                    (mean - 1.0).abs() < 0.02,
+                    "{:?} with {}: {}",
+                    integrator,
+                    name,
+                    mean
+                );
+            }
+        }
+    }
+
+    /// Sky light through a glass ball that is only partly in the
+    /// caustic bounds has to be counted once, either by the photons
+    /// or by the camera paths.
+    #[test]
+    pub fn caustics_straddling_the_bounds() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, -100.0, 0.0),
+            100.0,
+            Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5)),
+        ));
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, 1.2, 0.0),
+            0.5,
+            Dielectric::new_arc(1.5),
+        ));
+        // around the top of the ball, light leaves through the rest
+        scene.set_caustic_bounds(Point3::new(0.0, 1.6, 0.0), 0.35);
+        scene.set_environment(Color::rgb(1.0, 1.0, 1.0));
+
+        let runs = 100_000;
+        let mean = |integrator: Integrator, scene: &mut Scene| {
+            integrator.prepare(scene, (0.0, 1.0), 200_000, 0.05);
+            let mut sampler = SamplerKind::Sobol.build(runs, 0);
+            let sum = (0..runs)
+                .map(|run| {
+                    sampler.start_pixel_sample(0, 0, run);
+                    // the ground under the ball, seen from the side
+                    let (x, z) = sampler.get_2d();
+                    let target = Point3::new(1.2 * x - 0.6, 0.0, 1.2 * z - 0.6);
+                    let origin = Point3::new(0.0, 0.3, 3.0);
+                    let ray = Ray::new(origin, target - origin, 0.0);
+                    integrator
+                        .ray_color(&ray, scene, 16, sampler.as_mut())
+                        .luminance()
+                })
+                .sum::<f64>();
+            sum / runs as f64
+        };
+        let path = mean(Integrator::Path, &mut scene);
+        let photon = mean(Integrator::Photon, &mut scene);
+        assert!(
+            (photon / path - 1.0).abs() < 0.02,
+            "photon mapping {}, path tracing {}",
+            photon,
+            path
+        );
+    }
+
+    /// Connections through a mix of a matte and a specular material
+    /// only count the matte part, the specular one is left to the
+    /// paths that sampled it.
+    #[test]
+    pub fn mixed_specular_is_connected_like_path_tracing() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, -100.0, 0.0),
+            100.0,
+            Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5)),
+        ));
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            // mostly glass, reflecting the light
+            Mix::new_arc(
+                Lambertian::new_arc(Color::rgb(0.2, 0.1, 0.1)),
+                Dielectric::new_arc(1.5),
+                MixWeight::Constant(0.8),
+            ),
+        ));
+        scene.add_light(Sphere::new_arc(
+            Point3::new(2.0, 4.0, 2.0),
+            1.5,
+            DiffuseLight::new_arc(Color::rgb(4.0, 4.0, 4.0)),
+        ));
+        scene.set_environment(Color::rgb(0.0, 0.0, 0.0));
+
+        let runs = 100_000;
+        let mean = |integrator: Integrator| {
+            let mut sampler = SamplerKind::Sobol.build(runs, 0);
+            let sum = (0..runs)
+                .map(|run| {
+                    sampler.start_pixel_sample(0, 0, run);
+                    // the ball and the ground around it
+                    let (x, y) = sampler.get_2d();
+                    let target = Point3::new(3.0 * x - 1.5, 2.5 * y - 0.5, 0.0);
+                    let origin = Point3::new(0.0, 1.0, 5.0);
+                    let ray = Ray::new(origin, target - origin, 0.0);
+                    integrator
+                        .ray_color(&ray, &scene, 16, sampler.as_mut())
+                        .luminance()
+                })
+                .sum::<f64>();
+            sum / runs as f64
+        };
+        let path = mean(Integrator::Path);
+        let bidirectional = mean(Integrator::Bidirectional);
+        assert!(
+            (bidirectional / path - 1.0).abs() < 0.03,
+            "bidirectional {}, path tracing {}",
+            bidirectional,
+            path
+        );
+    }
+}
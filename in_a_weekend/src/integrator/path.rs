@@ -0,0 +1,74 @@
+use super::{background, russian_roulette, MAX_MEDIUM_STEPS, T_MIN};
+use crate::{
+    color::Color,
+    ray::Ray,
+    sampler::Sampler,
+    scene::Scene,
+    stats::{self, PathEnd},
+};
+
+pub fn ray_color(ray: &Ray, scene: &Scene, depth: usize, sampler: &mut dyn Sampler) -> Color {
+    let (direct, indirect) = trace(ray, scene, depth, sampler);
+    direct + indirect
+}
+
+/// Light along `ray' split into what is seen directly or after one
+/// bounce, and what took more bounces.
+pub fn trace(ray: &Ray, scene: &Scene, depth: usize, sampler: &mut dyn Sampler) -> (Color, Color) {
+    let mut direct = Color::rgb(0.0, 0.0, 0.0);
+    let mut indirect = Color::rgb(0.0, 0.0, 0.0);
+    // how much of the light arriving along `ray' reaches the camera
+    let mut throughput = Color::rgb(1.0, 1.0, 1.0);
+    let mut ray = ray.spawn(ray.origin(), ray.dir());
+
+    let mut bounce = 0;
+    let mut medium_steps = 0;
+    while bounce < depth {
+        let light = if bounce <= 1 {
+            &mut direct
+        } else {
+            &mut indirect
+        };
+        let rec = match scene.hit(&ray, T_MIN, f64::INFINITY) {
+            Some(rec) => rec,
+            None => {
+                *light += throughput * background(scene, &ray);
+                stats::path_ended(bounce, PathEnd::Escaped);
+                return (direct, indirect);
+            }
+        };
+        *light += throughput * rec.material().emitted(&rec);
+
+        let scatter = match rec.material().scatter(&ray, &rec, sampler) {
+            Some(scatter) => scatter,
+            // There was a hit, but it is absorbed
+            None => {
+                stats::path_ended(bounce + 1, PathEnd::Absorbed);
+                return (direct, indirect);
+            }
+        };
+        throughput = throughput * scatter.attenuation();
+        let next = scatter.ray();
+        ray = next.spawn(next.origin(), next.dir());
+
+        if scatter.in_medium() {
+            medium_steps += 1;
+            if medium_steps == MAX_MEDIUM_STEPS {
+                stats::path_ended(bounce + 1, PathEnd::DepthLimit);
+                return (direct, indirect);
+            }
+            continue;
+        }
+        let max = throughput.r().max(throughput.g()).max(throughput.b());
+        match russian_roulette(bounce, max, sampler.get_1d()) {
+            Some(survivor_scale) => throughput = throughput.scale(survivor_scale),
+            None => {
+                stats::path_ended(bounce + 1, PathEnd::Roulette);
+                return (direct, indirect);
+            }
+        }
+        bounce += 1;
+    }
+    stats::path_ended(depth, PathEnd::DepthLimit);
+    (direct, indirect)
+}
@@ -0,0 +1,297 @@
+//! Caustics from a photon map, after "Realistic Image Synthesis Using
+//! Photon Mapping" by Jensen.
+//!
+//! Before rendering, photons are shot from the sky and the lights.
+//! Those that reach a diffuse surface through specular ones are kept.
+//! Camera paths look up the photons around their diffuse hits, and
+//! don't count the light they find through specular bounces after a
+//! diffuse one, that light is already in the map.
+//!
+//! Photons from the sky are only aimed at the caustic bounds, so sky
+//! light through specular bounces is only in the map if the specular
+//! bounce next to the sky is in the bounds.  Camera paths check the
+//! same bounce, the last one before leaving the scene.
+//!
+//! Materials without `eval' count as specular.  Glossy ones have it
+//! and count as diffuse, photons land on them like on matte surfaces.
+//! Materials mixing both keep the photons landing on them and also
+//! pass them on when their specular part is sampled, camera paths
+//! likewise go on as after a specular bounce then.
+
+use std::f64::consts::PI;
+
+use super::{
+    background, emission_at, pick_light, russian_roulette, sun_dir, MAX_MEDIUM_STEPS, SUN_EXPONENT,
+    T_MIN,
+};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    onb::Onb,
+    photon_map::{Photon, PhotonMap},
+    ray::Ray,
+    sampler::{Sampler, SamplerKind},
+    sampling::{concentric_disk, cosine_hemisphere, uniform_sphere, uniform_sphere_pdf},
+    scene::Scene,
+    stats::{self, PathEnd},
+    v3::Vec3,
+};
+
+/// Bounces a photon can take before it is dropped.
+const PHOTON_DEPTH: usize = 16;
+
+/// Photons used for the density estimate at a hit.
+const GATHER_COUNT: usize = 64;
+
+/// What the camera path did since its last diffuse bounce.
+#[derive(Clone, Copy)]
+enum Since {
+    /// There wasn't one yet.
+    Start,
+    Diffuse,
+    /// Specular bounces, the latest one was or wasn't in the caustic
+    /// bounds of the scene.
+    Specular {
+        in_bounds: bool,
+    },
+}
+
+pub fn ray_color(ray: &Ray, scene: &Scene, depth: usize, sampler: &mut dyn Sampler) -> Color {
+    let mut color = Color::rgb(0.0, 0.0, 0.0);
+    let mut throughput = Color::rgb(1.0, 1.0, 1.0);
+    let mut ray = ray.spawn(ray.origin(), ray.dir());
+    let mut since = Since::Start;
+
+    let mut bounce = 0;
+    let mut medium_steps = 0;
+    while bounce < depth {
+        let rec = match scene.hit(&ray, T_MIN, f64::INFINITY) {
+            Some(rec) => rec,
+            None => {
+                stats::path_ended(bounce, PathEnd::Escaped);
+                return match since {
+                    // photons from the sky were aimed at these
+                    Since::Specular { in_bounds: true } => color,
+                    _ => color + throughput * background(scene, &ray),
+                };
+            }
+        };
+
+        let material = rec.material();
+        if !matches!(since, Since::Specular { .. }) {
+            color += throughput * material.emitted(&rec);
+        }
+        let wo = -ray.dir().normalized();
+        if material.eval(&rec, wo, wo).is_some() {
+            color += throughput * caustic(scene, &rec, wo);
+        }
+
+        let scatter = match material.scatter(&ray, &rec, sampler) {
+            Some(scatter) => scatter,
+            None => {
+                stats::path_ended(bounce + 1, PathEnd::Absorbed);
+                return color;
+            }
+        };
+        if !scatter.is_specular() {
+            since = Since::Diffuse;
+        } else if let Since::Diffuse | Since::Specular { .. } = since {
+            since = Since::Specular {
+                in_bounds: scene.in_caustic_bounds(rec.point()),
+            };
+        }
+        throughput = throughput * scatter.attenuation();
+        let next = scatter.ray();
+        ray = next.spawn(next.origin(), next.dir());
+
+        if scatter.in_medium() {
+            medium_steps += 1;
+            if medium_steps == MAX_MEDIUM_STEPS {
+                stats::path_ended(bounce + 1, PathEnd::DepthLimit);
+                return color;
+            }
+            continue;
+        }
+        let max = throughput.r().max(throughput.g()).max(throughput.b());
+        match russian_roulette(bounce, max, sampler.get_1d()) {
+            Some(survivor_scale) => throughput = throughput.scale(survivor_scale),
+            None => {
+                stats::path_ended(bounce + 1, PathEnd::Roulette);
+                return color;
+            }
+        }
+        bounce += 1;
+    }
+    stats::path_ended(depth, PathEnd::DepthLimit);
+    color
+}
+
+/// Light reflected towards `wo' estimated from the density of the
+/// photons around the hit.
+fn caustic(scene: &Scene, rec: &HitRecord, wo: Vec3) -> Color {
+    let (map, gather_radius) = match scene.caustics() {
+        Some(caustics) => caustics,
+        None => return Color::rgb(0.0, 0.0, 0.0),
+    };
+    let nearest = map.nearest(rec.point(), GATHER_COUNT, gather_radius);
+    // with few photons around the whole disc is used, so stray ones
+    // don't turn into bright dots
+    let radius_squared = if nearest.len() == GATHER_COUNT {
+        nearest.iter().map(|(d, _)| *d).fold(0.0, f64::max)
+    } else {
+        gather_radius * gather_radius
+    };
+    if radius_squared == 0.0 {
+        return Color::rgb(0.0, 0.0, 0.0);
+    }
+
+    let material = rec.material();
+    let mut sum = Color::rgb(0.0, 0.0, 0.0);
+    for (_, photon) in nearest {
+        // skip photons on the other side of thin surfaces
+        if Vec3::dot(photon.normal, rec.normal()) <= 0.5 {
+            continue;
+        }
+        if let Some(f) = material.eval(rec, wo, photon.from) {
+            sum += f * photon.power;
+        }
+    }
+    sum.scale(1.0 / (PI * radius_squared))
+}
+
+/// Shoot `count' photons from the sky and the lights of `scene' at
+/// random times while the shutter is open, and keep the caustic ones.
+/// The photons are spread like the samples of a single pixel.
+pub fn trace_caustics(scene: &Scene, open: f64, close: f64, count: usize) -> PhotonMap {
+    let mut sampler = SamplerKind::Halton.build(count, open.to_bits());
+    let lights = scene.lights();
+    let from_sky = scene.caustic_bounds().is_some();
+    let sky_probability = match (from_sky, lights.is_empty()) {
+        (false, true) => return PhotonMap::new(Vec::new()),
+        (true, true) => 1.0,
+        (false, false) => 0.0,
+        (true, false) => 0.5,
+    };
+
+    let mut photons = Vec::new();
+    for photon in 0..count {
+        sampler.start_pixel_sample(0, 0, photon);
+        let time = open + sampler.get_1d() * (close - open);
+        let sky = sampler.get_1d() < sky_probability;
+        let (ray, power) = if sky {
+            match sky_photon(scene, time, sampler.as_mut()) {
+                Some((ray, power)) => (ray, power.scale(1.0 / sky_probability)),
+                None => continue,
+            }
+        } else {
+            let (ray, power) = light_photon(scene, time, sampler.as_mut());
+            (ray, power.scale(1.0 / (1.0 - sky_probability)))
+        };
+        let power = power.scale(1.0 / count as f64);
+        trace_photon(scene, ray, power, sky, &mut photons, sampler.as_mut());
+    }
+    PhotonMap::new(photons)
+}
+
+/// A photon coming from the sky towards the caustic bounds of the
+/// scene, from a disc in front of them.
+fn sky_photon(scene: &Scene, time: f64, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+    let (center, radius) = scene.caustic_bounds()?;
+
+    // half of the photons come from around the sun
+    let sun = Onb::from_w(sun_dir());
+    let choice = sampler.get_1d();
+    let (u1, u2) = sampler.get_2d();
+    let towards_sky = if choice < 0.5 {
+        uniform_sphere((u1, u2))
+    } else {
+        let cos_theta = u1.powf(1.0 / (SUN_EXPONENT as f64 + 1.0));
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        sun.to_world(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    };
+    let cos_sun = Vec3::dot(towards_sky, sun_dir()).max(0.0);
+    let pdf = 0.5 * uniform_sphere_pdf()
+        + 0.5 * (SUN_EXPONENT as f64 + 1.0) / (2.0 * PI) * cos_sun.powi(SUN_EXPONENT);
+
+    let (x, y) = concentric_disk(sampler.get_2d());
+    let disc = Onb::from_w(towards_sky).to_world(Vec3::new(x, y, 0.0).scale(radius));
+    let origin = center + towards_sky.scale(radius) + disc;
+    let ray = Ray::new(origin, -towards_sky, time);
+
+    let radiance = background(scene, &Ray::new(center, towards_sky, time));
+    Some((ray, radiance.scale(PI * radius * radius / pdf)))
+}
+
+/// A photon leaving a random light of the scene.
+fn light_photon(scene: &Scene, time: f64, sampler: &mut dyn Sampler) -> (Ray, Color) {
+    let lights = scene.lights();
+    let light = pick_light(lights, sampler.get_1d());
+    let (u1, u2) = sampler.get_2d();
+    let (point, normal) = light.sample_surface(u1, u2);
+
+    // cosine weighted direction, cancels out with the cosine of the
+    // emission
+    let dir = Onb::from_w(normal).to_world(cosine_hemisphere(sampler.get_2d()));
+
+    let emission = emission_at(light, point, normal, time);
+    let power = emission.scale(PI * light.area() * lights.len() as f64);
+    (Ray::new(point, dir, time), power)
+}
+
+/// Follow a photon through specular bounces, adding it to `photons'
+/// on every surface with a BSDF it reaches after at least one of them.
+/// Photons from the sky also have to bounce off the caustic bounds
+/// first, camera paths count the rest of the sky themselves.
+fn trace_photon(
+    scene: &Scene,
+    mut ray: Ray,
+    mut power: Color,
+    from_sky: bool,
+    photons: &mut Vec<Photon>,
+    sampler: &mut dyn Sampler,
+) {
+    let mut in_bounds = None;
+    let mut bounce = 0;
+    let mut medium_steps = 0;
+    while bounce < PHOTON_DEPTH && medium_steps < MAX_MEDIUM_STEPS {
+        let rec = match scene.hit(&ray, T_MIN, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return,
+        };
+        let material = rec.material();
+        let from = -ray.dir().normalized();
+        let caustic = in_bounds.is_some_and(|in_bounds| in_bounds || !from_sky);
+        if caustic && material.eval(&rec, from, from).is_some() {
+            photons.push(Photon {
+                point: rec.point(),
+                normal: rec.normal(),
+                from,
+                power,
+            });
+        }
+
+        let scatter = match material.scatter(&ray, &rec, sampler) {
+            Some(scatter) => scatter,
+            None => return,
+        };
+        // only the specular part carries caustics on, the camera
+        // paths look up what the rest reflects
+        if !scatter.is_specular() {
+            return;
+        }
+        in_bounds.get_or_insert_with(|| scene.in_caustic_bounds(rec.point()));
+        power = power * scatter.attenuation();
+        let next = scatter.ray();
+        ray = next.spawn(next.origin(), next.dir());
+        if scatter.in_medium() {
+            medium_steps += 1;
+        } else {
+            bounce += 1;
+        }
+    }
+}
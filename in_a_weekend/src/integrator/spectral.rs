@@ -0,0 +1,85 @@
+use super::{background, russian_roulette, MAX_MEDIUM_STEPS, T_MIN};
+use crate::{
+    color::Color,
+    ray::Ray,
+    sampler::Sampler,
+    scene::Scene,
+    spectrum::{self, Sampled, Wavelengths, SAMPLES},
+    stats::{self, PathEnd},
+};
+
+pub fn ray_color(ray: &Ray, scene: &Scene, depth: usize, sampler: &mut dyn Sampler) -> Color {
+    let wavelengths = Wavelengths::sample(sampler.get_1d());
+    let ray = ray
+        .spawn(ray.origin(), ray.dir())
+        .with_wavelengths(wavelengths);
+    let (radiance, wavelengths) = trace(ray, scene, depth, sampler);
+    spectrum::to_rgb(radiance, &wavelengths)
+}
+
+/// Radiance along `ray' at its wavelengths, together with the
+/// wavelengths at the end of the path.  Those tell if the secondary
+/// wavelengths were dropped on the way.
+fn trace(
+    mut ray: Ray,
+    scene: &Scene,
+    depth: usize,
+    sampler: &mut dyn Sampler,
+) -> (Sampled, Wavelengths) {
+    let mut radiance = [0.0; SAMPLES];
+    let mut throughput = [1.0; SAMPLES];
+
+    let mut bounce = 0;
+    let mut medium_steps = 0;
+    while bounce < depth {
+        let wavelengths = ray.wavelengths().expect("Spectral ray without wavelengths");
+        let rec = match scene.hit(&ray, T_MIN, f64::INFINITY) {
+            Some(rec) => rec,
+            None => {
+                let sky = spectrum::sample_rgb(background(scene, &ray), &wavelengths);
+                let radiance = std::array::from_fn(|i| radiance[i] + throughput[i] * sky[i]);
+                stats::path_ended(bounce, PathEnd::Escaped);
+                return (radiance, wavelengths);
+            }
+        };
+        let emitted = spectrum::sample_rgb(rec.material().emitted(&rec), &wavelengths);
+        radiance = std::array::from_fn(|i| radiance[i] + throughput[i] * emitted[i]);
+
+        let scatter = match rec.material().scatter(&ray, &rec, sampler) {
+            Some(scatter) => scatter,
+            // absorbed
+            None => {
+                stats::path_ended(bounce + 1, PathEnd::Absorbed);
+                return (radiance, wavelengths);
+            }
+        };
+        let attenuation = spectrum::sample_rgb(scatter.attenuation(), &wavelengths);
+        throughput = std::array::from_fn(|i| throughput[i] * attenuation[i]);
+        let next = scatter.ray();
+        ray = next.spawn(next.origin(), next.dir());
+
+        if scatter.in_medium() {
+            medium_steps += 1;
+            if medium_steps == MAX_MEDIUM_STEPS {
+                stats::path_ended(bounce + 1, PathEnd::DepthLimit);
+                break;
+            }
+            continue;
+        }
+        let max = throughput.iter().copied().fold(0.0, f64::max);
+        match russian_roulette(bounce, max, sampler.get_1d()) {
+            Some(survivor_scale) => throughput = throughput.map(|t| t * survivor_scale),
+            None => {
+                stats::path_ended(bounce + 1, PathEnd::Roulette);
+                break;
+            }
+        }
+        bounce += 1;
+        if bounce == depth {
+            stats::path_ended(depth, PathEnd::DepthLimit);
+        }
+    }
+    // the last scatter may have dropped the secondary wavelengths
+    let wavelengths = ray.wavelengths().expect("Spectral ray without wavelengths");
+    (radiance, wavelengths)
+}
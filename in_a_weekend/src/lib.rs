@@ -0,0 +1,80 @@
+//! A ray tracer based on _Ray Tracing in One Weekend_.
+//!
+//! A [`Scene`] of [`Hittable`] shapes with [`Material`]s is rendered
+//! through a [`Camera`] by a [`Renderer`] into a [`Film`], which writes
+//! the images as PPM files.
+//!
+//! ```
+//! use rt_iaw::{
+//!     hittable::{material::lambertian::Lambertian, sphere::Sphere},
+//!     Camera, Color, Filter, Integrator, Point3, Rect, RenderSettings, Renderer, SampleCount,
+//!     SamplerKind, Scene, Vec3,
+//! };
+//!
+//! let mut scene = Scene::new();
+//! scene.add(Sphere::new_arc(
+//!     Point3::new(0.0, 0.0, -1.0),
+//!     0.5,
+//!     Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5)),
+//! ));
+//! let camera = Camera::new(
+//!     Point3::new(0.0, 0.0, 1.0),
+//!     Point3::new(0.0, 0.0, -1.0),
+//!     Vec3::new(0.0, 1.0, 0.0),
+//! );
+//! let settings = RenderSettings {
+//!     size: Rect { width: 8, height: 8 },
+//!     integrator: Integrator::Path,
+//!     samples: SampleCount::Fixed(4),
+//!     filter: Filter::default(),
+//!     sampler: SamplerKind::Sobol,
+//!     max_depth: 8,
+//!     photons: 10_000,
+//!     photon_radius: 0.05,
+//!     aovs: Vec::new(),
+//! };
+//!
+//! let film = Renderer::new(settings).with_seed(1).render(&mut scene, &camera);
+//! assert_eq!(film.beauty().len(), 64);
+//! ```
+
+pub mod animation;
+pub mod camera;
+pub mod color;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod integrator;
+pub mod onb;
+pub mod p3;
+mod photon_map;
+pub mod ppm;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod sampling;
+pub mod scene;
+mod spectrum;
+pub mod stats;
+pub mod texture;
+pub mod v3;
+
+pub use camera::Camera;
+pub use color::Color;
+pub use film::{Aov, Film, SampleCount};
+pub use filter::Filter;
+pub use hittable::{material::Material, Hittable};
+pub use integrator::Integrator;
+pub use p3::Point3;
+pub use render::{RenderSettings, Renderer};
+pub use sampler::SamplerKind;
+pub use scene::Scene;
+pub use v3::Vec3;
+
+/// Size of an image, or the position of a pixel in it.
+#[derive(Clone)]
+pub struct Rect {
+    pub width: usize,
+    pub height: usize,
+}
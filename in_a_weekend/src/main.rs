@@ -1,67 +1,39 @@
-mod camera;
-mod color;
-mod hittable;
-mod p3;
-mod ppm;
-mod ray;
-mod v3;
-
-use color::Color;
-use hittable::Hittable;
-use p3::Point3;
-use ppm::Ppm;
-use rand::Rng;
-use ray::Ray;
-use std::{f64::{consts::PI, INFINITY}, sync::{Arc, RwLock}};
-use v3::Vec3;
-
-use crate::{
-    camera::Camera,
+//! Renders an animation of the scene from the book into numbered PPM
+//! files in the working directory.
+
+mod options;
+mod progress;
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    f64::consts::PI,
+    hash::{Hash, Hasher},
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rt_iaw::{
+    animation::{AnimationFile, Interpolation, Track},
+    camera,
     hittable::{
-        list::HittableList,
-        material::{dielectric::Dielectric, lambertian::Lambertian, metal::Metal},
+        instance::{Instance, Transform},
+        material::{
+            dielectric::Dielectric,
+            lambertian::Lambertian,
+            metal::Metal,
+            mix::{Mix, MixWeight},
+        },
+        moving_sphere::MovingSphere,
         sphere::Sphere,
     },
+    stats::{self, Stats},
+    texture::{Checker, SolidColor},
+    Camera, Color, Film, Point3, Rect, RenderSettings, Renderer, SampleCount, Scene, Vec3,
 };
 
-#[derive(Clone)]
-pub struct Rect {
-    width: usize,
-    height: usize,
-}
-
-fn ray_color(ray: &Ray, world: &dyn Hittable, depth: usize) -> Color {
-    if depth == 0 {
-        return Color::rgb(0.0, 0.0, 0.0);
-    }
-
-    // eprintln!("Ray {:?} at depth: {}", *ray, depth);
-    // avoid self bounces
-    let min = 0.001;
-    if let Some(rec) = world.hit(ray, min, INFINITY) {
-        // eprintln!("There was a hit for {:?}", rec);
-        if let Some(scatter) = rec.material().as_ref().scatter(ray, &rec) {
-            // eprintln!("Scattered ray is: {:?}", scatter);
-            return scatter.attenuation() * ray_color(scatter.ray(), world, depth - 1);
-        }
-        // There was a hit, but it is absorbed
-        // eprintln!("Ray was absorbed");
-        return Color::rgb(0.0, 0.0, 0.0);
-    }
-    // eprint!("No hit for ray");
-    let dir = ray.dir().normalized();
-    let t = 0.5 * (dir.y() + 1.0);
-    let mix_factor_sky_bottom = 1.0 - t;
-    let mix_factor_sky_top = t;
-    let sky_bottom_color = Color::rgb(1.0, 1.0, 1.0).into_gamma();
-    let sky_top_color = Color::rgb(0.5, 0.7, 1.0).into_gamma();
-    let sky_color = mix_factor_sky_bottom * sky_bottom_color + mix_factor_sky_top * sky_top_color;
-    let scaled_sky = sky_color.scale(1.0/16.0);
-    let sun_color = Color::rgb(1.0, 1.0, 0.9);
-    let sun_dir = Vec3::new(1.0, 1.0, -1.0).normalized();
-    let mix_factor_sun = Vec3::dot(sun_dir, dir).max(0.0).powi(8);
-    sun_color.scale(mix_factor_sun) + scaled_sky
-}
+use crate::{options::Options, progress::Progress};
 
 #[cfg(debug_assertions)]
 const SAMPLE_PER_PIXEL: usize = 10;
@@ -71,6 +43,14 @@ const SAMPLE_PER_PIXEL: usize = 2500;
 /// Number of ray bounces to calculate
 const MAX_DEPTH: usize = 50;
 
+/// Photons shot for every frame with the photon integrator.
+#[cfg(debug_assertions)]
+const PHOTONS: usize = 20_000;
+#[cfg(not(debug_assertions))]
+const PHOTONS: usize = 500_000;
+/// Photons farther than this from a hit don't light it.
+const PHOTON_RADIUS: f64 = 0.05;
+
 #[cfg(debug_assertions)]
 const IMAGE_WIDTH: usize = 400;
 #[cfg(not(debug_assertions))]
@@ -80,88 +60,318 @@ const IMAGE_HEIGHT: usize = (IMAGE_WIDTH as f64 / camera::ASPECT_RATIO) as usize
 
 const WORKER_THREADS: usize = 8;
 
+const FPS: usize = 60;
+const SCENE_LEN_SEC: usize = 3;
+const FRAMES: usize = FPS * SCENE_LEN_SEC;
+
+/// How often the samples summed up for a frame are saved, so an
+/// interrupted render can go on from there.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Fraction of the frame time the shutter is open.  Half of it is
+/// the classic 180 degree shutter.
+const SHUTTER: f64 = 0.5;
+
+/// Everything in the scene that changes over time.
+struct Animation {
+    camera_from: Track<Point3>,
+    camera_at: Track<Point3>,
+    left_fuzz: Track<f64>,
+    rolling_color: Track<Color>,
+    right_hop: Track<Transform>,
+}
+
+impl Animation {
+    fn new() -> Self {
+        let scene_len = SCENE_LEN_SEC as f64;
+        let camera_focus = Point3::new(0.0, 0.0, -1.0);
+        let cam_radius = 2.5;
+
+        // keyframes around a circle, smoothed into an orbit
+        const ORBIT_KEYS: usize = 12;
+        let camera_from = (0..=ORBIT_KEYS)
+            .fold(Track::new(), |track, key| {
+                let tau = key as f64 * 2.0 * PI / ORBIT_KEYS as f64;
+                let position =
+                    Point3::new(f64::sin(tau) * cam_radius, 0.0, f64::cos(tau) * cam_radius)
+                        + camera_focus.into()
+                        + Vec3::new(0.0, 1.0, 0.0);
+                let time = key as f64 * scene_len / ORBIT_KEYS as f64;
+                track.key(time, position, Interpolation::CatmullRom)
+            })
+            .looping();
+
+        let left_fuzz = Track::new()
+            .key(0.0, 0.05, Interpolation::Bezier(0.05, 0.6))
+            .key(scene_len * 0.5, 0.6, Interpolation::Bezier(0.6, 0.05))
+            .key(scene_len, 0.05, Interpolation::Step);
+
+        let rolling_color = Track::new()
+            .key(0.0, Color::rgb(0.7, 0.1, 0.1), Interpolation::Linear)
+            .key(scene_len, Color::rgb(0.1, 0.1, 0.7), Interpolation::Step);
+
+        // hops once while the camera goes around
+        let hop = |height| Transform::new(Vec3::new(0.0, height, 0.0), 0.0);
+        let right_hop = Track::new()
+            .key(scene_len * 0.4, hop(0.0), Interpolation::Bezier(hop(0.3), hop(0.4)))
+            .key(scene_len * 0.5, hop(0.4), Interpolation::Bezier(hop(0.4), hop(0.3)))
+            .key(scene_len * 0.6, hop(0.0), Interpolation::Step);
+
+        Self {
+            camera_from,
+            camera_at: Track::constant(camera_focus),
+            left_fuzz,
+            rolling_color,
+            right_hop,
+        }
+    }
+
+    /// Replace the tracks described in `file'.
+    fn read_tracks(&mut self, file: &AnimationFile) -> io::Result<()> {
+        for name in file.names() {
+            match name {
+                "camera_from" => self.camera_from = file.track(name)?,
+                "camera_at" => self.camera_at = file.track(name)?,
+                "left_fuzz" => self.left_fuzz = file.track(name)?,
+                "rolling_color" => self.rolling_color = file.track(name)?,
+                "right_hop" => self.right_hop = file.track(name)?,
+                other => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown track {}", other),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn camera(&self, shutter_open: f64, shutter_close: f64) -> Camera {
+        let view_up = Vec3::new(0.0, 1.0, 0.0);
+        let at = |time| (self.camera_from.at(time), self.camera_at.at(time));
+        let (from, to) = at(shutter_open);
+        let (moved_from, moved_to) = at(shutter_close);
+        Camera::new(from, to, view_up)
+            .moving_to(moved_from, moved_to, view_up)
+            .with_shutter(shutter_open, shutter_close)
+    }
+
+    /// The scene at `time`.  Material parameters are fixed for the
+    /// frame, transforms are evaluated for each ray.
+    fn scene(&self, time: f64) -> Scene {
+        let scene_len = SCENE_LEN_SEC as f64;
+        let mut scene = Scene::new();
+        let checker = Checker::new_arc(
+            SolidColor::new_arc(Color::rgb(1.0, 1.0, 1.0)),
+            SolidColor::new_arc(Color::rgb(0.0, 0.0, 0.0)),
+            0.4,
+        );
+        let matte_ground = Mix::new_arc(
+            Lambertian::new_arc(Color::rgb(0.8, 0.8, 0.0)),
+            Lambertian::new_arc(Color::rgb(0.4, 0.4, 0.0)),
+            MixWeight::Texture(checker),
+        );
+        let glass_center = Dielectric::new_arc(1.5);
+        let metal_left = Metal::new_arc(Color::rgb(0.8, 0.8, 0.8), self.left_fuzz.at(time));
+        let metal_right = Metal::new_arc(Color::rgb(0.8, 0.6, 0.2), 0.7);
+        let matte_rolling = Lambertian::new_arc(self.rolling_color.at(time));
+
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            matte_ground,
+        ));
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            glass_center,
+        ));
+        scene.add(Sphere::new_arc(
+            Point3::new(-1.1, 0.0, -1.0),
+            0.5,
+            metal_left,
+        ));
+        scene.add(Instance::new_arc(
+            Sphere::new_arc(Point3::new(1.1, 0.0, -1.0), 0.5, metal_right),
+            self.right_hop.clone(),
+        ));
+        scene.add(MovingSphere::new_arc(
+            (Point3::new(-0.8, -0.3, -0.2), 0.0),
+            (Point3::new(0.8, -0.3, -0.2), scene_len),
+            0.2,
+            matte_rolling,
+        ));
+        scene.set_caustic_bounds(Point3::new(0.0, 0.0, -1.0), 1.7);
+        scene
+    }
+}
+
+/// Name of the images of `frame' without the extension.
+fn out_file_name(frame: usize) -> String {
+    format!("out_{:05}", frame)
+}
+
 fn main() -> std::io::Result<()> {
-    let mut world = HittableList::new();
-    let matte_ground = Lambertian::new_arc(Color::rgb(0.8, 0.8, 0.0));
-    let glass_center = Dielectric::new_arc(1.5);
-    let metal_left = Metal::new_arc(Color::rgb(0.8, 0.8, 0.8), 0.05);
-    let metal_right = Metal::new_arc(Color::rgb(0.8, 0.6, 0.2), 0.7);
-
-    world.add(Sphere::new_arc(
-        Point3::new(0.0, -100.5, -1.0),
-        100.0,
-        matte_ground,
-    ));
-    world.add(Sphere::new_arc(
-        Point3::new(0.0, 0.0, -1.0),
-        0.5,
-        glass_center,
-    ));
-    world.add(Sphere::new_arc(
-        Point3::new(-1.1, 0.0, -1.0),
-        0.5,
-        metal_left,
-    ));
-    world.add(Sphere::new_arc(
-        Point3::new(1.1, 0.0, -1.0),
-        0.5,
-        metal_right,
-    ));
+    let options = Options::from_args();
+    let mut animation = Animation::new();
+    // frames are told apart by their seed, the animations by this.
+    // Changes to the built in one are up to the user.
+    let mut animation_key = "built in animation".to_string();
+    if let Some(path) = &options.animation {
+        let contents = std::fs::read(path)?;
+        animation.read_tracks(&AnimationFile::read(contents.as_slice())?)?;
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        animation_key = format!("animation {:016x}", hasher.finish());
+    }
 
-    let ppm = Ppm::new(Rect {
+    let size = Rect {
         height: IMAGE_HEIGHT,
         width: IMAGE_WIDTH,
-    });
-
-    eprintln!("Using samples per pixel: {}", SAMPLE_PER_PIXEL);
-
-    let camera_focus = Point3::new(0.0, 0.0, -1.0);
-    let cam_radius = 2.5;
+    };
 
-    const FPS: usize = 60;
-    const SCENE_LEN_SEC: usize = 3;
-    const FRAMES: usize = FPS * SCENE_LEN_SEC;
+    match options.samples {
+        SampleCount::Fixed(samples) => eprintln!("Using samples per pixel: {}", samples),
+        SampleCount::Adaptive {
+            min,
+            max,
+            max_error,
+        } => eprintln!(
+            "Using {} to {} samples per pixel, up to a relative error of {}",
+            min, max, max_error
+        ),
+    }
 
     let pool = threadpool::ThreadPool::new(WORKER_THREADS);
-    let world = Arc::new(RwLock::new(world));
+    let settings = RenderSettings {
+        size,
+        integrator: options.integrator,
+        samples: options.samples,
+        filter: options.filter.clone(),
+        sampler: options.sampler,
+        max_depth: MAX_DEPTH,
+        photons: PHOTONS,
+        photon_radius: PHOTON_RADIUS,
+        aovs: options.aovs.clone(),
+    };
+    // frames on disk are done, their beauty is written last
+    let frames: Vec<usize> = (0..FRAMES)
+        .filter(|frame| !Path::new(&format!("{}.ppm", out_file_name(*frame))).exists())
+        .collect();
+    if frames.len() < FRAMES {
+        eprintln!(
+            "Skipping {} frames that are already rendered",
+            FRAMES - frames.len()
+        );
+    }
+
+    let start = Instant::now();
+    let progress = Arc::new(Progress::new(
+        frames.len(),
+        IMAGE_HEIGHT * settings.passes(),
+    ));
+    // summed over the frames, with the time each of them took
+    let totals = Arc::new(Mutex::new((Stats::default(), Vec::new())));
 
-    for frame in 0..FRAMES {
-        let tau = frame as f64 * 2.0 * PI / FRAMES as f64;
-        let origin = Point3::new(f64::sin(tau) * cam_radius, 0.0, f64::cos(tau) * cam_radius)
-            + camera_focus.into()
-            + Vec3::new(0.0, 1.0, 0.0);
-        let camera = Camera::new(origin, camera_focus, Vec3::new(0.0, 1.0, 0.0));
+    for frame in frames {
+        let shutter_open = frame as f64 / FPS as f64;
+        let shutter_close = shutter_open + SHUTTER / FPS as f64;
+        let camera = animation.camera(shutter_open, shutter_close);
 
-        let ppm = ppm.clone();
-        let world = world.clone();
+        let settings = settings.clone();
+        let animation_key = animation_key.clone();
+        let denoiser = options.denoiser;
+        let progress = progress.clone();
+        let totals = totals.clone();
+        let mut scene = animation.scene(shutter_open);
 
         pool.execute(move || {
-            let color_for_position = move |Rect { width, height }| {
-                let world = world.as_ref().read().expect("Couldn't read world");
-                let mut rng = rand::thread_rng();
-                let mut color = Color::rgb(0.0, 0.0, 0.0);
-                for _sample_number in 1..SAMPLE_PER_PIXEL {
-                    let u = (width as f64 + rng.gen::<f64>()) / (IMAGE_WIDTH - 1) as f64;
-                    let v = (height as f64 + rng.gen::<f64>()) / (IMAGE_HEIGHT - 1) as f64;
-                    let dir = camera.dir(u, v);
-                    let r = Ray::new(camera.origin(), dir);
-                    color += ray_color(&r, &*world, MAX_DEPTH);
+            let render_start = Instant::now();
+            let out_file_name = out_file_name(frame);
+            let checkpoint_name = format!("{}.checkpoint", out_file_name);
+            let seed = frame as u64;
+            let key = format!("{} {}", settings.key(seed), animation_key);
+            let mut renderer = Renderer::new(settings.clone()).with_seed(seed);
+            match Film::load_checkpoint(
+                &checkpoint_name,
+                &key,
+                settings.size.clone(),
+                &settings.aovs,
+            ) {
+                Ok(Some((film, samples))) => {
+                    progress.println(&format!(
+                        "{}: going on from {} samples per pixel",
+                        out_file_name, samples
+                    ));
+                    progress.skip_rows(IMAGE_HEIGHT * settings.passes_for(samples));
+                    renderer = renderer.resuming(film, samples);
+                }
+                Ok(None) => {
+                    progress.println(&format!(
+                        "{}: checkpoint is from other settings, starting over",
+                        out_file_name
+                    ));
                 }
-                color.sampled(SAMPLE_PER_PIXEL).scale(16.0).reinhard().gamma_corrected()
-            };
-
-            let out_file_name = format!("out_{:05}.ppm", frame);
-            let mut out_file = std::fs::OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(out_file_name.clone())
-                .unwrap_or_else(|_| panic!("Can't open file for writing: {}", out_file_name));
-            ppm.write(&mut out_file, color_for_position)
-                .unwrap_or_else(|_| panic!("Couldn't write PPM for: {}", out_file_name))
-            // eprintln!("{:?}", ray_color(&Ray::new(camera.origin(), camera.dir(0.5, 0.5)), &world, MAX_DEPTH));
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    progress.println(&format!(
+                        "{}: couldn't read checkpoint, starting over: {}",
+                        out_file_name, error
+                    ));
+                }
+            }
+
+            let mut saved = Instant::now();
+            let film = renderer
+                .on_row(|| progress.row_done())
+                .on_pass(|film, samples| {
+                    if samples < settings.samples.max() && saved.elapsed() >= CHECKPOINT_INTERVAL {
+                        if let Err(error) = film.save_checkpoint(&checkpoint_name, &key, samples) {
+                            progress.println(&format!(
+                                "{}: couldn't save checkpoint: {}",
+                                out_file_name, error
+                            ));
+                        }
+                        saved = Instant::now();
+                    }
+                })
+                .render(&mut scene, &camera);
+            let frame_time = render_start.elapsed();
+
+            if film.dropped() > 0 {
+                progress.println(&format!(
+                    "{}: dropped {} samples that weren't finite",
+                    out_file_name,
+                    film.dropped()
+                ));
+            }
+            if let SampleCount::Adaptive { max, .. } = settings.samples {
+                let heatmap_file_name = format!("{}_spp.ppm", out_file_name);
+                film.write_sample_heatmap(&heatmap_file_name, max)
+                    .unwrap_or_else(|_| panic!("Couldn't write PPM for: {}", heatmap_file_name));
+            }
+            if let Some(denoiser) = denoiser {
+                let denoised = denoiser.denoise(&film);
+                let denoised_file_name = format!("{}_denoised.ppm", out_file_name);
+                film.write_like_beauty(&denoised_file_name, &denoised)
+                    .unwrap_or_else(|_| panic!("Couldn't write PPM for: {}", denoised_file_name));
+            }
+            film.write(&out_file_name)
+                .unwrap_or_else(|_| panic!("Couldn't write PPM for: {}", out_file_name));
+            // the frame is done, nothing to go on from
+            let _ = std::fs::remove_file(&checkpoint_name);
+
+            let mut totals = totals.lock().unwrap();
+            totals.0 += stats::take();
+            totals.1.push(frame_time);
+            progress.frame_done();
         });
     }
     pool.join();
+    progress.finish();
+
+    let totals = totals.lock().unwrap();
+    let (stats, frame_times) = &*totals;
+    eprintln!("{}", stats.report(start.elapsed(), frame_times));
     Ok(())
 }
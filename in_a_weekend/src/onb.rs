@@ -0,0 +1,34 @@
+use crate::v3::Vec3;
+
+/// Orthonormal basis with `w' pointing along a given direction.
+/// Local coordinates have `z' along `w'.
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    /// `w' has to be a unit vector.
+    pub fn from_w(w: Vec3) -> Self {
+        // "Building an Orthonormal Basis, Revisited" by Duff et al.
+        let sign = 1.0_f64.copysign(w.z());
+        let a = -1.0 / (sign + w.z());
+        let b = w.x() * w.y() * a;
+        let u = Vec3::new(1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x());
+        let v = Vec3::new(b, sign + w.y() * w.y() * a, -w.y());
+        Self { u, v, w }
+    }
+
+    pub fn to_world(&self, local: Vec3) -> Vec3 {
+        self.u.scale(local.x()) + self.v.scale(local.y()) + self.w.scale(local.z())
+    }
+
+    pub fn to_local(&self, world: Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(world, self.u),
+            Vec3::dot(world, self.v),
+            Vec3::dot(world, self.w),
+        )
+    }
+}
@@ -0,0 +1,141 @@
+use rt_iaw::{
+    denoise::{Denoiser, GUIDES},
+    film::{Aov, SampleCount},
+    filter::{Filter, FilterKind},
+    integrator::Integrator,
+    sampler::SamplerKind,
+};
+
+use crate::SAMPLE_PER_PIXEL;
+
+/// Settings picked on the command line for a render.
+pub struct Options {
+    pub integrator: Integrator,
+    /// Extra images written next to the beauty.
+    pub aovs: Vec<Aov>,
+    /// Filter for a denoised copy of the beauty.
+    pub denoiser: Option<Denoiser>,
+    pub samples: SampleCount,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    /// File with tracks replacing the built in ones.
+    pub animation: Option<String>,
+}
+
+/// Samples every pixel gets with adaptive sampling if not set.
+const DEFAULT_MIN_SAMPLES: usize = 16;
+
+const USAGE: &str = "Usage: rt_iaw [options]
+  --integrator path|spectral|bdpt|photon
+  --aovs all|<aov>,...      albedo, normal, depth, position, material_id,
+                            object_id, direct, indirect
+  --denoise <radius>
+  --adaptive <max error>    stop sampling pixels at this relative error
+  --min-spp <samples>       samples before stopping is considered
+  --filter box|tent|gaussian|mitchell|blackman-harris
+  --filter-radius <pixels>
+  --sampler independent|stratified|halton|sobol
+  --animation <file>        keyframes for camera_from, camera_at,
+                            left_fuzz, rolling_color, right_hop";
+
+impl Options {
+    pub fn from_args() -> Self {
+        let mut options = Options {
+            integrator: Integrator::Path,
+            aovs: Vec::new(),
+            denoiser: None,
+            samples: SampleCount::Fixed(SAMPLE_PER_PIXEL),
+            filter: Filter::default(),
+            sampler: SamplerKind::Sobol,
+            animation: None,
+        };
+        let mut filter_kind = FilterKind::Box;
+        let mut filter_radius = None;
+        let mut max_error = None;
+        let mut min_samples = DEFAULT_MIN_SAMPLES;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .unwrap_or_else(|| exit_with_usage(&format!("Missing value for {}", arg)))
+            };
+            match arg.as_str() {
+                "--integrator" => {
+                    options.integrator = match value().as_str() {
+                        "path" => Integrator::Path,
+                        "spectral" => Integrator::Spectral,
+                        "bdpt" => Integrator::Bidirectional,
+                        "photon" => Integrator::Photon,
+                        other => exit_with_usage(&format!("Unknown integrator: {}", other)),
+                    }
+                }
+                "--aovs" => {
+                    options.aovs = match value().as_str() {
+                        "all" => Aov::ALL.to_vec(),
+                        names => names
+                            .split(',')
+                            .map(|name| {
+                                Aov::from_name(name).unwrap_or_else(|| {
+                                    exit_with_usage(&format!("Unknown AOV: {}", name))
+                                })
+                            })
+                            .collect(),
+                    }
+                }
+                "--denoise" => {
+                    let radius = parse(&arg, &value());
+                    if radius == 0 {
+                        exit_with_usage("The denoise radius has to be at least 1");
+                    }
+                    options.denoiser = Some(Denoiser::with_radius(radius));
+                }
+                "--adaptive" => max_error = Some(parse(&arg, &value())),
+                "--min-spp" => min_samples = parse(&arg, &value()),
+                "--filter" => {
+                    let name = value();
+                    filter_kind = FilterKind::from_name(&name)
+                        .unwrap_or_else(|| exit_with_usage(&format!("Unknown filter: {}", name)))
+                }
+                "--filter-radius" => filter_radius = Some(parse(&arg, &value())),
+                "--sampler" => {
+                    let name = value();
+                    options.sampler = SamplerKind::from_name(&name)
+                        .unwrap_or_else(|| exit_with_usage(&format!("Unknown sampler: {}", name)))
+                }
+                "--animation" => options.animation = Some(value()),
+                other => exit_with_usage(&format!("Unknown argument: {}", other)),
+            }
+        }
+
+        let filter_radius = filter_radius.unwrap_or_else(|| filter_kind.default_radius());
+        options.filter = Filter::new(filter_kind, filter_radius);
+        if let Some(max_error) = max_error {
+            options.samples = SampleCount::Adaptive {
+                min: min_samples.min(SAMPLE_PER_PIXEL),
+                max: SAMPLE_PER_PIXEL,
+                max_error,
+            };
+        }
+        if options.denoiser.is_some() {
+            // the denoiser is guided by these
+            for guide in GUIDES {
+                if !options.aovs.contains(&guide) {
+                    options.aovs.push(guide);
+                }
+            }
+        }
+        options
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| exit_with_usage(&format!("Bad value for {}: {}", arg, value)))
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(2)
+}
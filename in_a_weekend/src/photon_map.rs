@@ -0,0 +1,222 @@
+//! Photons left on surfaces, stored in a kd-tree to find the ones
+//! nearest to a point.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::{color::Color, p3::Point3, v3::Vec3};
+
+pub struct Photon {
+    pub point: Point3,
+    /// Surface normal where the photon landed.
+    pub normal: Vec3,
+    /// Unit vector towards where the photon came from.
+    pub from: Vec3,
+    pub power: Color,
+}
+
+/// Balanced kd-tree without explicit nodes.  The middle element of
+/// every range splits the rest of it along its axis.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Up to `k' photons nearest to `point' that are closer than
+    /// `max_distance', with their squared distances.  In no
+    /// particular order.
+    pub fn nearest(&self, point: Point3, k: usize, max_distance: f64) -> Vec<(f64, &Photon)> {
+        let mut found = BinaryHeap::with_capacity(k + 1);
+        let mut max_squared = max_distance * max_distance;
+        if k > 0 {
+            search(
+                &self.photons,
+                &self.axes,
+                point,
+                k,
+                &mut found,
+                &mut max_squared,
+            );
+        }
+        found
+            .into_iter()
+            .map(|candidate| (candidate.distance_squared, candidate.photon))
+            .collect()
+    }
+}
+
+fn coordinate(point: Point3, axis: usize) -> f64 {
+    let (x, y, z) = point.xyz();
+    match axis {
+        0 => x,
+        1 => y,
+        _ => z,
+    }
+}
+
+fn build(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+    let axis = widest_axis(photons);
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| {
+        coordinate(a.point, axis)
+            .partial_cmp(&coordinate(b.point, axis))
+            .unwrap_or(Ordering::Equal)
+    });
+    axes[mid] = axis;
+
+    let (left, rest) = photons.split_at_mut(mid);
+    let (left_axes, rest_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut rest[1..], &mut rest_axes[1..]);
+}
+
+fn widest_axis(photons: &[Photon]) -> usize {
+    let extent = |axis| {
+        let (min, max) =
+            photons
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), photon| {
+                    let c = coordinate(photon.point, axis);
+                    (min.min(c), max.max(c))
+                });
+        max - min
+    };
+    (0..3)
+        .max_by(|&a, &b| extent(a).partial_cmp(&extent(b)).unwrap_or(Ordering::Equal))
+        .unwrap_or(0)
+}
+
+/// Ordered by distance so the heap has the farthest one on top.
+struct Candidate<'a> {
+    distance_squared: f64,
+    photon: &'a Photon,
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance_squared == other.distance_squared
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared
+            .partial_cmp(&other.distance_squared)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+fn search<'a>(
+    photons: &'a [Photon],
+    axes: &[usize],
+    point: Point3,
+    k: usize,
+    found: &mut BinaryHeap<Candidate<'a>>,
+    max_squared: &mut f64,
+) {
+    if photons.is_empty() {
+        return;
+    }
+    let mid = photons.len() / 2;
+    let photon = &photons[mid];
+    let axis = axes[mid];
+    let delta = coordinate(point, axis) - coordinate(photon.point, axis);
+
+    let (left, right) = (&photons[..mid], &photons[mid + 1..]);
+    let (left_axes, right_axes) = (&axes[..mid], &axes[mid + 1..]);
+    let ((near, near_axes), (far, far_axes)) = if delta < 0.0 {
+        ((left, left_axes), (right, right_axes))
+    } else {
+        ((right, right_axes), (left, left_axes))
+    };
+
+    search(near, near_axes, point, k, found, max_squared);
+
+    let distance_squared = (photon.point - point).length_squared();
+    if distance_squared < *max_squared {
+        found.push(Candidate {
+            distance_squared,
+            photon,
+        });
+        if found.len() > k {
+            found.pop();
+        }
+        if found.len() == k {
+            if let Some(farthest) = found.peek() {
+                *max_squared = farthest.distance_squared;
+            }
+        }
+    }
+
+    if delta * delta < *max_squared {
+        search(far, far_axes, point, k, found, max_squared);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    pub fn nearest_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut random_point = || Point3::new(rng.gen(), rng.gen(), rng.gen());
+        let points: Vec<Point3> = (0..500).map(|_| random_point()).collect();
+        let queries: Vec<Point3> = (0..20).map(|_| random_point()).collect();
+        let map = PhotonMap::new(
+            points
+                .iter()
+                .map(|&point| Photon {
+                    point,
+                    normal: Vec3::new(0.0, 1.0, 0.0),
+                    from: Vec3::new(0.0, 1.0, 0.0),
+                    power: Color::rgb(1.0, 1.0, 1.0),
+                })
+                .collect(),
+        );
+
+        for query in queries {
+            let mut expected: Vec<f64> = points
+                .iter()
+                .map(|&point| (point - query).length_squared())
+                .filter(|&d| d < 0.3 * 0.3)
+                .collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(10);
+
+            let mut found: Vec<f64> = map
+                .nearest(query, 10, 0.3)
+                .iter()
+                .map(|(d, _)| *d)
+                .collect();
+            found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(expected, found);
+        }
+    }
+}
@@ -29,6 +29,48 @@ impl Ppm {
     }
 }
 
+/// Read a plain PPM like the ones written by `Ppm::write'.  Returns
+/// the size and the pixels row by row from the bottom, with channels
+/// in `[0, 1]'.
+pub fn read<R>(reader: &mut R) -> std::io::Result<(Rect, Vec<Color>)>
+where
+    R: std::io::Read,
+{
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let mut tokens = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(str::split_whitespace);
+    if tokens.next() != Some("P3") {
+        return Err(invalid("not a plain PPM"));
+    }
+    let mut number = || -> std::io::Result<usize> {
+        tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| invalid("bad or missing number"))
+    };
+    let (width, height, max) = (number()?, number()?, number()?);
+    if max == 0 {
+        return Err(invalid("zero maximum value"));
+    }
+
+    let mut rows = Vec::with_capacity(height);
+    for _ in 0..height {
+        let mut row = Vec::with_capacity(width);
+        for _ in 0..width {
+            let mut channel = || -> std::io::Result<f64> { Ok(number()? as f64 / max as f64) };
+            row.push(Color::rgb(channel()?, channel()?, channel()?));
+        }
+        rows.push(row);
+    }
+    // the file starts at the top
+    let pixels = rows.into_iter().rev().flatten().collect();
+    Ok((Rect { width, height }, pixels))
+}
+
 fn write_color<W>(color: &Color, writer: &mut W) -> std::io::Result<()>
 where
     W: std::io::Write,
@@ -39,3 +81,32 @@ where
     let ib = (color.b() * SCALE_TO_256) as u8;
     writeln!(writer, "{} {} {}", ir, ig, ib)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn read_what_was_written() {
+        let size = Rect {
+            width: 3,
+            height: 2,
+        };
+        let color_at =
+            |pixel: Rect| Color::rgb(pixel.width as f64 / 4.0, pixel.height as f64 / 2.0, 0.5);
+        let mut file = Vec::new();
+        Ppm::new(size.clone()).write(&mut file, color_at).unwrap();
+
+        let (read_size, pixels) = read(&mut file.as_slice()).unwrap();
+        assert_eq!((read_size.width, read_size.height), (3, 2));
+        for (index, pixel) in pixels.iter().enumerate() {
+            let expected = color_at(Rect {
+                width: index % 3,
+                height: index / 3,
+            });
+            assert!((pixel.r() - expected.r()).abs() < 1.0 / 255.0);
+            assert!((pixel.g() - expected.g()).abs() < 1.0 / 255.0);
+            assert!((pixel.b() - expected.b()).abs() < 1.0 / 255.0);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Progress bar on stderr for a render, shared by the threads doing
+//! the work.
+
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use rt_iaw::stats::format_duration;
+
+const BAR_WIDTH: usize = 30;
+/// The bar isn't drawn more often than this.
+const REDRAW: Duration = Duration::from_millis(250);
+
+pub struct Progress {
+    frames: usize,
+    rows_per_frame: usize,
+    rows_done: AtomicUsize,
+    /// Rows counted as done without rendering them, they don't count
+    /// for how fast the rest is going.
+    rows_skipped: AtomicUsize,
+    frames_done: AtomicUsize,
+    start: Instant,
+    /// When the bar was last drawn, also keeps the threads from
+    /// drawing over each other.
+    drawn: Mutex<Option<Instant>>,
+}
+
+impl Progress {
+    pub fn new(frames: usize, rows_per_frame: usize) -> Self {
+        Self {
+            frames,
+            rows_per_frame,
+            rows_done: AtomicUsize::new(0),
+            rows_skipped: AtomicUsize::new(0),
+            frames_done: AtomicUsize::new(0),
+            start: Instant::now(),
+            drawn: Mutex::new(None),
+        }
+    }
+
+    pub fn row_done(&self) {
+        self.rows_done.fetch_add(1, Ordering::Relaxed);
+        self.draw(false);
+    }
+
+    /// Count `rows' as done that were rendered before, like the
+    /// passes loaded from a checkpoint.
+    pub fn skip_rows(&self, rows: usize) {
+        self.rows_done.fetch_add(rows, Ordering::Relaxed);
+        self.rows_skipped.fetch_add(rows, Ordering::Relaxed);
+        self.draw(true);
+    }
+
+    pub fn frame_done(&self) {
+        self.frames_done.fetch_add(1, Ordering::Relaxed);
+        self.draw(true);
+    }
+
+    /// Print `message' on its own line above the bar.
+    pub fn println(&self, message: &str) {
+        let _drawn = self.drawn.lock().unwrap();
+        eprint!("\r\x1b[K{}\n{}", message, self.bar());
+    }
+
+    /// Leave the bar as it is and go on below it.
+    pub fn finish(&self) {
+        let _drawn = self.drawn.lock().unwrap();
+        eprintln!("\r\x1b[K{}", self.bar());
+    }
+
+    fn draw(&self, force: bool) {
+        let mut drawn = self.drawn.lock().unwrap();
+        let now = Instant::now();
+        if !force && drawn.is_some_and(|drawn| now - drawn < REDRAW) {
+            return;
+        }
+        *drawn = Some(now);
+        eprint!("\r\x1b[K{}", self.bar());
+        let _ = std::io::stderr().flush();
+    }
+
+    fn bar(&self) -> String {
+        let total = (self.frames * self.rows_per_frame).max(1);
+        let done = self.rows_done.load(Ordering::Relaxed).min(total);
+        let fraction = done as f64 / total as f64;
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+
+        let elapsed = self.start.elapsed();
+        let rendered = done - self.rows_skipped.load(Ordering::Relaxed).min(done);
+        let eta = if rendered > 0 {
+            let left = elapsed.mul_f64((total - done) as f64 / rendered as f64);
+            format!("ETA {}", format_duration(left))
+        } else {
+            "ETA unknown".to_string()
+        };
+        format!(
+            "[{}{}] {:3.0}% {}/{} frames, {} elapsed, {}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            100.0 * fraction,
+            self.frames_done.load(Ordering::Relaxed),
+            self.frames,
+            format_duration(elapsed),
+            eta
+        )
+    }
+}
@@ -1,15 +1,42 @@
 use crate::p3::Point3;
+use crate::spectrum::Wavelengths;
 use crate::v3::Vec3;
 
 #[derive(Debug)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
+    time: f64,
+    /// Only set when rendering spectrally.
+    wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
-    pub fn new(orig: Point3, dir: Vec3) -> Self {
-        Ray { orig, dir }
+    pub fn new(orig: Point3, dir: Vec3, time: f64) -> Self {
+        Ray {
+            orig,
+            dir,
+            time,
+            wavelengths: None,
+        }
+    }
+
+    pub fn with_wavelengths(self, wavelengths: Wavelengths) -> Self {
+        Ray {
+            wavelengths: Some(wavelengths),
+            ..self
+        }
+    }
+
+    /// A ray continuing the path of `self`, e.g. after a scatter.
+    /// It keeps the time and wavelengths of the parent ray.
+    pub fn spawn(&self, orig: Point3, dir: Vec3) -> Self {
+        Ray {
+            orig,
+            dir,
+            time: self.time,
+            wavelengths: self.wavelengths,
+        }
     }
 
     pub fn dir(&self) -> Vec3 {
@@ -23,4 +50,12 @@ impl Ray {
     pub fn origin(&self) -> Point3 {
         self.orig
     }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn wavelengths(&self) -> Option<Wavelengths> {
+        self.wavelengths
+    }
 }
@@ -0,0 +1,220 @@
+use crate::{
+    camera::Camera,
+    film::{Aov, Film, SampleCount},
+    filter::Filter,
+    integrator::Integrator,
+    sampler::SamplerKind,
+    scene::Scene,
+    Rect,
+};
+
+/// Samples every pixel gets in one pass over the frame.
+const PASS_SAMPLES: usize = 16;
+
+/// Everything about how a frame is rendered, apart from what is in it.
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub size: Rect,
+    pub integrator: Integrator,
+    pub samples: SampleCount,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    /// Bounces a path can take at most.
+    pub max_depth: usize,
+    /// Photons shot for every frame, only used by the photon
+    /// integrator.
+    pub photons: usize,
+    /// Photons farther than this from a hit don't light it, in the
+    /// units of the scene.  Smaller gives sharper but noisier
+    /// caustics.
+    pub photon_radius: f64,
+    /// Extra images rendered next to the beauty.
+    pub aovs: Vec<Aov>,
+}
+
+impl RenderSettings {
+    /// Passes over the frame until every pixel has its samples.
+    pub fn passes(&self) -> usize {
+        self.passes_for(self.samples.max())
+    }
+
+    /// Passes over the frame that take `samples' per pixel.
+    pub fn passes_for(&self, samples: usize) -> usize {
+        samples.div_ceil(PASS_SAMPLES)
+    }
+
+    /// Tells apart the settings and `seed' that give different
+    /// samples, films can only be continued with the same key.  The
+    /// scene and the camera aren't part of it, callers add what tells
+    /// theirs apart.
+    pub fn key(&self, seed: u64) -> String {
+        format!(
+            "{}x{} {:?} {:?} {:?} depth {} photons {} within {} {:?} {} {} seed {}",
+            self.size.width,
+            self.size.height,
+            self.integrator,
+            self.samples,
+            self.sampler,
+            self.max_depth,
+            self.photons,
+            self.photon_radius,
+            self.aovs,
+            self.filter.kind().name(),
+            self.filter.radius(),
+            seed
+        )
+    }
+}
+
+/// Renders a frame with some settings.  Built up like the camera,
+/// starting from `new'.
+pub struct Renderer<'a> {
+    settings: RenderSettings,
+    seed: u64,
+    resume: Option<(Film, usize)>,
+    row_done: Box<dyn Fn() + 'a>,
+    pass_done: PassDone<'a>,
+}
+
+type PassDone<'a> = Box<dyn FnMut(&Film, usize) + 'a>;
+
+impl<'a> Renderer<'a> {
+    pub fn new(settings: RenderSettings) -> Self {
+        Self {
+            settings,
+            seed: 0,
+            resume: None,
+            row_done: Box::new(|| {}),
+            pass_done: Box::new(|_, _| {}),
+        }
+    }
+
+    /// Every random number comes from a sampler seeded with `seed',
+    /// the same seed gives the same film.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Go on from `film', rendered with the same settings and seed in
+    /// passes that took `samples' per pixel, as if it was never
+    /// stopped.
+    pub fn resuming(self, film: Film, samples: usize) -> Self {
+        Self {
+            resume: Some((film, samples)),
+            ..self
+        }
+    }
+
+    /// Call `row_done' after every row of every pass.
+    pub fn on_row(self, row_done: impl Fn() + 'a) -> Self {
+        Self {
+            row_done: Box::new(row_done),
+            ..self
+        }
+    }
+
+    /// Call `pass_done' after every pass with the film so far and its
+    /// samples per pixel.
+    pub fn on_pass(self, pass_done: impl FnMut(&Film, usize) + 'a) -> Self {
+        Self {
+            pass_done: Box::new(pass_done),
+            ..self
+        }
+    }
+
+    /// Render `scene' as seen through `camera', after preparing the
+    /// scene for the integrator.
+    ///
+    /// The samples are taken in passes over the frame, every pixel
+    /// gets up to `PASS_SAMPLES' more in a pass.
+    pub fn render(mut self, scene: &mut Scene, camera: &Camera) -> Film {
+        let settings = &self.settings;
+        settings.integrator.prepare(
+            scene,
+            camera.shutter(),
+            settings.photons,
+            settings.photon_radius,
+        );
+
+        let Rect { width, height } = settings.size;
+        let sample_count = settings.samples;
+        let (mut film, mut samples_done) = self
+            .resume
+            .take()
+            .unwrap_or_else(|| (Film::new(settings.size.clone(), &settings.aovs), 0));
+        let mut sampler = settings.sampler.build(sample_count.max(), self.seed);
+        while samples_done < sample_count.max() {
+            let pass_end = (samples_done + PASS_SAMPLES).min(sample_count.max());
+            for y in 0..height {
+                for x in 0..width {
+                    let index = film.index(x, y);
+                    for sample_number in samples_done..pass_end {
+                        if sample_count.is_done(&film, index) {
+                            break;
+                        }
+                        sampler.start_pixel_sample(x, y, sample_number);
+                        let (u1, u2) = sampler.get_2d();
+                        let (dx, dy, weight) = settings.filter.sample(u1, u2);
+                        let u = (x as f64 + 0.5 + dx) / width as f64;
+                        let v = (y as f64 + 0.5 + dy) / height as f64;
+                        let r = camera.ray(u, v, sampler.get_1d());
+                        let (color, aov_values) = settings.integrator.sample(
+                            &r,
+                            scene,
+                            settings.max_depth,
+                            &settings.aovs,
+                            sampler.as_mut(),
+                        );
+                        film.add(x, y, weight, color, &aov_values);
+                    }
+                }
+                (self.row_done)();
+            }
+            samples_done = pass_end;
+            (self.pass_done)(&film, samples_done);
+        }
+        film
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::{material::lambertian::Lambertian, sphere::Sphere},
+        p3::Point3,
+        v3::Vec3,
+    };
+
+    #[test]
+    pub fn renders_frames_of_any_size() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new_arc(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5)),
+        ));
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        for (width, height) in [(0, 0), (1, 1), (1, 3)] {
+            let settings = RenderSettings {
+                size: Rect { width, height },
+                integrator: Integrator::Path,
+                samples: SampleCount::Fixed(4),
+                filter: Filter::default(),
+                sampler: SamplerKind::Sobol,
+                max_depth: 8,
+                photons: 0,
+                photon_radius: 0.05,
+                aovs: Vec::new(),
+            };
+            let film = Renderer::new(settings).render(&mut scene, &camera);
+            assert_eq!(film.beauty().len(), width * height);
+            assert_eq!(film.dropped(), 0, "{}x{}", width, height);
+        }
+    }
+}
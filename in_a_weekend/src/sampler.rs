@@ -0,0 +1,412 @@
+//! Sources of the numbers in `[0, 1)' that paths are built from.
+//!
+//! Every sample of a pixel asks for its numbers one dimension at a
+//! time, in the same order: the position in the pixel, the time, then
+//! what the integrator and the materials need.  Low-discrepancy
+//! samplers spread each dimension evenly over the samples of a pixel.
+//! All of them are randomized by hashing the pixel, the dimension and a
+//! seed, so renders with the same seed are the same.
+
+/// Numbers for the samples of pixels.
+pub trait Sampler {
+    /// Start the `index'th sample of the pixel at `x', `y'.  The
+    /// dimensions start over from the first.
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize);
+
+    /// The next dimension.
+    fn get_1d(&mut self) -> f64;
+
+    /// The next two dimensions, spread evenly together where the
+    /// sampler can.
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// The samplers to pick from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// A sampler for up to `samples_per_pixel' samples in every pixel.
+    /// Samples past that are still fine, but not as evenly spread.
+    pub fn build(&self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler + Send> {
+        let state = State::new(samples_per_pixel, seed);
+        match self {
+            SamplerKind::Independent => Box::new(Independent(state)),
+            SamplerKind::Stratified => Box::new(Stratified(state)),
+            SamplerKind::Halton => Box::new(Halton(state)),
+            SamplerKind::Sobol => Box::new(Sobol(state)),
+        }
+    }
+}
+
+/// Where a sampler is in the sequence.
+struct State {
+    samples_per_pixel: usize,
+    seed: u64,
+    pixel: (u64, u64),
+    index: usize,
+    dimension: u64,
+}
+
+impl State {
+    fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x as u64, y as u64);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    /// Hash of the pixel, the dimension and the seed, the same for
+    /// every sample of the pixel.
+    fn dimension_hash(&self) -> u64 {
+        hash(&[self.pixel.0, self.pixel.1, self.dimension, self.seed])
+    }
+
+    /// Hash that also differs from sample to sample.
+    fn sample_hash(&self) -> u64 {
+        hash(&[
+            self.pixel.0,
+            self.pixel.1,
+            self.dimension,
+            self.seed,
+            self.index as u64,
+        ])
+    }
+
+    fn next_dimensions(&mut self, count: u64) {
+        self.dimension += count;
+    }
+}
+
+/// Independent uniform numbers.
+struct Independent(State);
+
+impl Sampler for Independent {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.0.start(x, y, index)
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let u = to_unit(self.0.sample_hash());
+        self.0.next_dimensions(1);
+        u
+    }
+}
+
+/// Jittered strata, shuffled for every dimension so the dimensions
+/// aren't correlated.
+struct Stratified(State);
+
+impl Stratified {
+    /// Jitter inside stratum `stratum' of `strata', or anywhere once
+    /// the samples run past the strata.
+    fn jittered(&self, strata: usize, salt: u64) -> (usize, f64) {
+        let jitter = to_unit(mix(self.0.sample_hash() ^ salt));
+        if self.0.index >= strata {
+            return (0, jitter);
+        }
+        let stratum = permutation_element(self.0.index, strata, self.0.dimension_hash());
+        (stratum, jitter)
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.0.start(x, y, index)
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let strata = self.0.samples_per_pixel;
+        let (stratum, jitter) = self.jittered(strata, 0);
+        let u = if self.0.index >= strata {
+            jitter
+        } else {
+            (stratum as f64 + jitter) / strata as f64
+        };
+        self.0.next_dimensions(1);
+        u.min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // a grid of about as many cells as samples
+        let nx = (self.0.samples_per_pixel as f64).sqrt().round().max(1.0) as usize;
+        let ny = self.0.samples_per_pixel.div_ceil(nx);
+        let (cell, jitter_x) = self.jittered(nx * ny, 0);
+        let (_, jitter_y) = self.jittered(nx * ny, 1);
+        let uv = if self.0.index >= nx * ny {
+            (jitter_x, jitter_y)
+        } else {
+            (
+                ((cell % nx) as f64 + jitter_x) / nx as f64,
+                ((cell / nx) as f64 + jitter_y) / ny as f64,
+            )
+        };
+        self.0.next_dimensions(2);
+        (uv.0.min(ONE_MINUS_EPSILON), uv.1.min(ONE_MINUS_EPSILON))
+    }
+}
+
+/// Owen scrambled Halton sequence.  Every dimension has its own prime
+/// base, dimensions past the primes get independent numbers.
+struct Halton(State);
+
+impl Sampler for Halton {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.0.start(x, y, index)
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let u = match PRIMES.get(self.0.dimension as usize) {
+            Some(&base) => {
+                scrambled_radical_inverse(base, self.0.index as u64, self.0.dimension_hash())
+            }
+            None => to_unit(self.0.sample_hash()),
+        };
+        self.0.next_dimensions(1);
+        u
+    }
+}
+
+/// First two dimensions of the Sobol sequence for every pair of
+/// dimensions, Owen scrambled and shuffled differently for each pair.
+/// Pairs are well spread, sample counts that are powers of two
+/// best.
+struct Sobol(State);
+
+impl Sobol {
+    fn index(&self) -> u32 {
+        let samples = self.0.samples_per_pixel;
+        let index = if self.0.index < samples {
+            permutation_element(self.0.index, samples, self.0.dimension_hash())
+        } else {
+            self.0.index
+        };
+        index as u32
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.0.start(x, y, index)
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.0.dimension_hash();
+        let u = owen_scramble(self.index().reverse_bits(), seed as u32);
+        self.0.next_dimensions(1);
+        bits_to_unit(u)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.0.dimension_hash();
+        let index = self.index();
+        let x = owen_scramble(index.reverse_bits(), seed as u32);
+        let y = owen_scramble(sobol_second_dimension(index), (seed >> 32) as u32);
+        self.0.next_dimensions(2);
+        (bits_to_unit(x), bits_to_unit(y))
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// 64 bit finalizer from MurmurHash3.
+fn mix(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+    h ^ (h >> 33)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9E37_79B9_7F4A_7C15, |h, &value| mix(h ^ mix(value)))
+}
+
+fn to_unit(h: u64) -> f64 {
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn bits_to_unit(bits: u32) -> f64 {
+    (bits as f64 / (1u64 << 32) as f64).min(ONE_MINUS_EPSILON)
+}
+
+/// Element `i' of a random permutation of `0..n' picked by `seed'.
+/// From "Correlated Multi-Jittered Sampling" by Kensler.
+fn permutation_element(i: usize, n: usize, seed: u64) -> usize {
+    if n <= 1 {
+        return 0;
+    }
+    let (mut i, l, p) = (i as u32, n as u32, seed as u32);
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xE170_893D);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_EB3F);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_FA69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74DC_B303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9E50_1CC3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xC860_A3DF);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    ((i.wrapping_add(p)) % l) as usize
+}
+
+/// Digits of `index' in `base' mirrored around the point, every digit
+/// permuted depending on the digits before it.
+fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse_base_power = 1.0;
+    let mut reversed = 0u64;
+    // until the digits are below the precision of the result, or one
+    // more wouldn't fit for large bases
+    let limit = u64::MAX / base - base;
+    while 1.0 - (base - 1) as f64 * inverse_base_power < 1.0 && reversed < limit {
+        let next = index / base;
+        let digit = index - next * base;
+        let digit_seed = mix(seed ^ reversed);
+        let digit = permutation_element(digit as usize, base as usize, digit_seed) as u64;
+        reversed = reversed * base + digit;
+        inverse_base_power *= inverse_base;
+        index = next;
+    }
+    (reversed as f64 * inverse_base_power).min(ONE_MINUS_EPSILON)
+}
+
+/// Bits of the second Sobol dimension, with the index in the low bits.
+fn sobol_second_dimension(index: u32) -> u32 {
+    // the generator matrix is Pascal's triangle mod 2
+    let mut direction = 1u32 << 31;
+    let mut bits = 0;
+    let mut index = index;
+    while index != 0 {
+        if index & 1 != 0 {
+            bits ^= direction;
+        }
+        direction ^= direction >> 1;
+        index >>= 1;
+    }
+    bits
+}
+
+/// Owen scrambling of a number with the bits of the result in `v',
+/// from "Practical Hash-based Owen Scrambling" by Burley.
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3D20_ADEA);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x0552_6C56);
+    v ^= v.wrapping_mul(0x53A2_2864);
+    v.reverse_bits()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn permutation_elements_are_a_permutation() {
+        for n in [1, 2, 7, 16, 100] {
+            let mut seen: Vec<usize> = (0..n).map(|i| permutation_element(i, n, 1234)).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..n).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    pub fn low_discrepancy_samplers_integrate_better() {
+        let samples = 64;
+        let pixels = 64;
+        // smooth in every dimension, integrates to 1
+        let f = |u: f64| 1.5 * (1.0 - (2.0 * u - 1.0).powi(2));
+        let rms_error = |kind: SamplerKind| {
+            let mut sampler = kind.build(samples, 5);
+            let mut squared_error = 0.0;
+            for pixel in 0..pixels {
+                let mut sum = 0.0;
+                for index in 0..samples {
+                    sampler.start_pixel_sample(pixel, 1, index);
+                    let (x, y) = sampler.get_2d();
+                    let later = sampler.get_1d();
+                    for u in [x, y, later].iter() {
+                        assert!((0.0..1.0).contains(u));
+                    }
+                    sum += f(x) * f(y) + f(later);
+                }
+                squared_error += (sum / samples as f64 - 2.0).powi(2);
+            }
+            (squared_error / pixels as f64).sqrt()
+        };
+
+        let independent = rms_error(SamplerKind::Independent);
+        for kind in SamplerKind::ALL {
+            let error = rms_error(kind);
+            if kind != SamplerKind::Independent {
+                assert!(error < independent / 3.0, "{:?}: {}", kind, error);
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Warps from points uniform in `[0, 1)^2' to directions and points on
+//! a disk, with their densities.  They are continuous and take exactly
+//! two numbers, so low-discrepancy points stay well spread after them.
+//!
+//! Directions on hemispheres and cones are around `z'.
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use crate::v3::Vec3;
+
+/// Uniform direction on the unit sphere.
+pub fn uniform_sphere((u1, u2): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Solid angle density of `uniform_sphere'.
+pub fn uniform_sphere_pdf() -> f64 {
+    1.0 / (4.0 * PI)
+}
+
+/// Uniform direction on the hemisphere with positive `z'.
+pub fn uniform_hemisphere((u1, u2): (f64, f64)) -> Vec3 {
+    let z = u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Solid angle density of `uniform_hemisphere'.
+pub fn uniform_hemisphere_pdf() -> f64 {
+    1.0 / (2.0 * PI)
+}
+
+/// Point on the unit disk, with the square mapped to it in rings so
+/// neighbouring points stay close.  "A Low Distortion Map Between Disk
+/// and Square" by Shirley and Chiu.
+pub fn concentric_disk((u1, u2): (f64, f64)) -> (f64, f64) {
+    let (x, y) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, FRAC_PI_2 - FRAC_PI_4 * (x / y))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Area density of `concentric_disk'.
+pub fn concentric_disk_pdf() -> f64 {
+    1.0 / PI
+}
+
+/// Direction on the hemisphere with positive `z', with a density
+/// proportional to the cosine to `z'.  A point on the disk lifted up to
+/// the hemisphere.
+pub fn cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let (x, y) = concentric_disk(u);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    Vec3::new(x, y, z)
+}
+
+/// Solid angle density of `cosine_hemisphere' giving `direction'.
+pub fn cosine_hemisphere_pdf(direction: Vec3) -> f64 {
+    direction.z().max(0.0) / PI
+}
+
+/// Uniform direction in the cone around `z' with the cosine of its half
+/// angle `cos_max'.
+pub fn uniform_cone((u1, u2): (f64, f64), cos_max: f64) -> Vec3 {
+    let z = 1.0 - u1 * (1.0 - cos_max);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Solid angle density of `uniform_cone'.
+pub fn uniform_cone_pdf(cos_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_max))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Points jittered in the cells of a fine grid of the square, every
+    /// warp has to spread them evenly.
+    fn grid() -> impl Iterator<Item = (f64, f64)> {
+        let n = 256;
+        let mut rng = StdRng::seed_from_u64(17);
+        (0..n * n).map(move |i| {
+            let mut cell = |k: usize| (k as f64 + rng.gen::<f64>()) / n as f64;
+            (cell(i % n), cell(i / n))
+        })
+    }
+
+    /// Fail unless `values' in `[0, 1)' fall into equal bins about
+    /// equally often.
+    fn assert_uniform(name: &str, values: impl Iterator<Item = f64>) {
+        const BINS: usize = 16;
+        let mut counts = [0usize; BINS];
+        let mut total = 0;
+        for value in values {
+            assert!((0.0..=1.0).contains(&value), "{}: {}", name, value);
+            counts[((value * BINS as f64) as usize).min(BINS - 1)] += 1;
+            total += 1;
+        }
+        let expected = total as f64 / BINS as f64;
+        for (bin, &count) in counts.iter().enumerate() {
+            let off = (count as f64 - expected).abs() / expected;
+            assert!(
+                off < 0.02,
+                "{}: bin {} has {} of {}",
+                name,
+                bin,
+                count,
+                total
+            );
+        }
+    }
+
+    /// Azimuth of `direction' scaled to `[0, 1]'.
+    fn azimuth(direction: Vec3) -> f64 {
+        (direction.y().atan2(direction.x()) + PI) / (2.0 * PI)
+    }
+
+    #[test]
+    pub fn sphere_and_hemisphere_are_uniform() {
+        let sphere: Vec<_> = grid().map(uniform_sphere).collect();
+        // the height on a sphere is uniform, after Archimedes
+        assert_uniform("sphere z", sphere.iter().map(|d| 0.5 * (d.z() + 1.0)));
+        assert_uniform("sphere phi", sphere.iter().map(|&d| azimuth(d)));
+        assert!(sphere
+            .iter()
+            .all(|d| (d.length_squared() - 1.0).abs() < 1e-9));
+
+        let hemisphere: Vec<_> = grid().map(uniform_hemisphere).collect();
+        assert_uniform("hemisphere z", hemisphere.iter().map(|d| d.z()));
+        assert_uniform("hemisphere phi", hemisphere.iter().map(|&d| azimuth(d)));
+    }
+
+    #[test]
+    pub fn disk_and_cosine_hemisphere_are_uniform() {
+        let disk: Vec<_> = grid().map(concentric_disk).collect();
+        assert_uniform("disk r^2", disk.iter().map(|(x, y)| x * x + y * y));
+        let angle = |(x, y): (f64, f64)| (y.atan2(x) + PI) / (2.0 * PI);
+        assert_uniform("disk angle", disk.iter().map(|&p| angle(p)));
+
+        // projected onto the disk a cosine distribution is uniform
+        let cosine: Vec<_> = grid().map(cosine_hemisphere).collect();
+        assert_uniform("cosine z^2", cosine.iter().map(|d| d.z() * d.z()));
+        assert_uniform("cosine phi", cosine.iter().map(|&d| azimuth(d)));
+    }
+
+    #[test]
+    pub fn cone_is_uniform_inside() {
+        let cos_max = 0.8;
+        let cone: Vec<_> = grid().map(|u| uniform_cone(u, cos_max)).collect();
+        assert_uniform(
+            "cone z",
+            cone.iter().map(|d| (d.z() - cos_max) / (1.0 - cos_max)),
+        );
+        assert_uniform("cone phi", cone.iter().map(|&d| azimuth(d)));
+    }
+
+    #[test]
+    pub fn densities_integrate_to_one() {
+        // Monte Carlo over the sphere with uniform directions
+        let sphere_integral = |pdf: &dyn Fn(Vec3) -> f64| {
+            let values: Vec<f64> = grid()
+                .map(uniform_sphere)
+                .map(|d| pdf(d) / uniform_sphere_pdf())
+                .collect();
+            values.iter().sum::<f64>() / values.len() as f64
+        };
+        let hemisphere = |d: Vec3| {
+            if d.z() > 0.0 {
+                uniform_hemisphere_pdf()
+            } else {
+                0.0
+            }
+        };
+        let cos_max = 0.5;
+        let cone = |d: Vec3| {
+            if d.z() > cos_max {
+                uniform_cone_pdf(cos_max)
+            } else {
+                0.0
+            }
+        };
+        assert!((sphere_integral(&|_| uniform_sphere_pdf()) - 1.0).abs() < 1e-3);
+        assert!((sphere_integral(&hemisphere) - 1.0).abs() < 1e-3);
+        assert!((sphere_integral(&cosine_hemisphere_pdf) - 1.0).abs() < 1e-3);
+        assert!((sphere_integral(&cone) - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    color::Color,
+    hittable::{list::HittableList, material::Material, sphere::Sphere, HitRecord, Hittable},
+    p3::Point3,
+    photon_map::PhotonMap,
+    ray::Ray,
+    stats,
+};
+
+/// The world to render together with the lights in it that can be
+/// sampled directly.
+#[derive(Default)]
+pub struct Scene {
+    world: HittableList,
+    lights: Vec<Arc<Sphere>>,
+    /// Numbers of the materials in the order they were added, by
+    /// address.  The objects of `world' keep them alive.
+    material_ids: HashMap<usize, usize>,
+    /// Center and radius of a sphere around the objects casting
+    /// caustics from the sky.
+    caustic_bounds: Option<(Point3, f64)>,
+    /// Photons and how far from a hit they count.
+    caustics: Option<(PhotonMap, f64)>,
+    /// Light arriving from every direction instead of the sky.
+    environment: Option<Color>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            world: HittableList::new(),
+            lights: Vec::new(),
+            material_ids: HashMap::new(),
+            caustic_bounds: None,
+            caustics: None,
+            environment: None,
+        }
+    }
+
+    pub fn add(&mut self, object: Arc<dyn Hittable + Send + Sync>) {
+        for material in object.materials() {
+            let next_id = self.material_ids.len();
+            self.material_ids
+                .entry(address(&material))
+                .or_insert(next_id);
+        }
+        self.world.add(object)
+    }
+
+    /// Add an emitting sphere.  It is part of the world like other
+    /// objects, but integrators can also start paths from it.
+    pub fn add_light(&mut self, light: Arc<Sphere>) {
+        self.add(light.clone());
+        self.lights.push(light)
+    }
+
+    /// Number of `material' among those of the scene, the same
+    /// for every scene built the same way.
+    pub fn material_id(&self, material: &Arc<dyn Material>) -> Option<usize> {
+        self.material_ids.get(&address(material)).copied()
+    }
+
+    pub fn world(&self) -> &dyn Hittable {
+        &self.world
+    }
+
+    /// Closest hit of `ray' with the world, counted as a ray cast.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        stats::ray_cast();
+        self.world.hit(ray, t_min, t_max)
+    }
+
+    pub fn lights(&self) -> &[Arc<Sphere>] {
+        &self.lights
+    }
+
+    /// Photons from the sky are only aimed at this sphere, caustics
+    /// of objects outside of it come from the lights alone.
+    pub fn set_caustic_bounds(&mut self, center: Point3, radius: f64) {
+        self.caustic_bounds = Some((center, radius))
+    }
+
+    pub fn caustic_bounds(&self) -> Option<(Point3, f64)> {
+        self.caustic_bounds
+    }
+
+    pub fn in_caustic_bounds(&self, point: Point3) -> bool {
+        self.caustic_bounds
+            .is_some_and(|(center, radius)| (point - center).length_squared() <= radius * radius)
+    }
+
+    /// Photons traced before rendering by integrators that need them,
+    /// only those within `gather_radius' of a hit light it.
+    pub fn set_caustics(&mut self, caustics: PhotonMap, gather_radius: f64) {
+        self.caustics = Some((caustics, gather_radius))
+    }
+
+    pub fn caustics(&self) -> Option<(&PhotonMap, f64)> {
+        self.caustics.as_ref().map(|(map, radius)| (map, *radius))
+    }
+
+    /// Light the scene evenly from all directions instead of with the
+    /// sky, like a furnace.
+    pub fn set_environment(&mut self, radiance: Color) {
+        self.environment = Some(radiance)
+    }
+
+    pub fn environment(&self) -> Option<Color> {
+        self.environment
+    }
+}
+
+/// Where `material' lives, to tell materials apart.
+fn address<M: Material + ?Sized>(material: &Arc<M>) -> usize {
+    Arc::as_ptr(material) as *const u8 as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hittable::material::lambertian::Lambertian;
+
+    #[test]
+    pub fn materials_are_numbered_as_added() {
+        let build = || {
+            let shared = Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5));
+            let mut scene = Scene::new();
+            for x in [-2.0, 2.0] {
+                scene.add(Sphere::new_arc(
+                    Point3::new(x, 0.0, 0.0),
+                    1.0,
+                    shared.clone(),
+                ));
+            }
+            scene.add(Sphere::new_arc(
+                Point3::new(0.0, 0.0, 0.0),
+                0.5,
+                Lambertian::new_arc(Color::rgb(0.1, 0.2, 0.3)),
+            ));
+            scene
+        };
+        let ids = |scene: &Scene| {
+            [-2.0, 0.0, 2.0].map(|x| {
+                let ray = Ray::new(
+                    Point3::new(x, 0.0, 5.0),
+                    Point3::new(x, 0.0, 0.0) - Point3::new(x, 0.0, 5.0),
+                    0.0,
+                );
+                let rec = scene.hit(&ray, 0.001, f64::INFINITY).unwrap();
+                scene.material_id(&rec.material())
+            })
+        };
+        assert_eq!(ids(&build()), [Some(0), Some(1), Some(0)]);
+        assert_eq!(ids(&build()), ids(&build()));
+    }
+}
@@ -0,0 +1,218 @@
+//! Conversions between RGB colors and spectral samples for the
+//! spectral integrator.  Wavelengths are in nanometres.
+
+use std::sync::OnceLock;
+
+use crate::color::Color;
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+/// Number of wavelengths carried by a path.
+pub const SAMPLES: usize = 4;
+
+/// Wavelengths sampled for a single path with hero wavelength
+/// sampling: the hero is uniform over the visible range and the
+/// others are rotated by equal steps from it.
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelengths {
+    lambda: [f64; SAMPLES],
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    /// `u' is uniform in `[0, 1)'.
+    pub fn sample(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f64 / SAMPLES as f64).fract();
+            *l = LAMBDA_MIN + offset * range;
+        }
+        Self {
+            lambda,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self) -> [f64; SAMPLES] {
+        self.lambda
+    }
+
+    /// Only the hero wavelength is followed from now on.  Needed when
+    /// the path takes a direction only valid for the hero, like
+    /// refraction in a dispersive medium.
+    pub fn terminate_secondary(self) -> Self {
+        Self {
+            secondary_terminated: true,
+            ..self
+        }
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.secondary_terminated
+    }
+}
+
+/// Values of a spectrum at the sampled wavelengths.
+pub type Sampled = [f64; SAMPLES];
+
+/// Smooth spectrum for an RGB value, evaluated at `lambda'.  White is
+/// mapped to the constant 1 and converting the spectrum back with
+/// `to_rgb' gives the original color.
+pub fn from_rgb(color: Color, lambda: f64) -> f64 {
+    let c = mat_mul(&tables().rgb_to_basis, [color.r(), color.g(), color.b()]);
+    let b = basis(lambda);
+    c[0] * b[0] + c[1] * b[1] + c[2] * b[2]
+}
+
+pub fn sample_rgb(color: Color, wavelengths: &Wavelengths) -> Sampled {
+    wavelengths.lambda.map(|l| from_rgb(color, l))
+}
+
+/// Monte Carlo estimate of the RGB color of a spectrum sampled at
+/// `wavelengths'.
+pub fn to_rgb(values: Sampled, wavelengths: &Wavelengths) -> Color {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let used = if wavelengths.secondary_terminated {
+        1
+    } else {
+        SAMPLES
+    };
+    let mut xyz = [0.0; 3];
+    for (value, lambda) in values.iter().zip(wavelengths.lambda.iter()).take(used) {
+        let cmf = cie_xyz(*lambda);
+        for (acc, c) in xyz.iter_mut().zip(cmf.iter()) {
+            *acc += value * c * range / used as f64;
+        }
+    }
+    let rgb = xyz_to_rgb(xyz);
+    Color::rgb(rgb[0], rgb[1], rgb[2])
+}
+
+/// Linear sRGB, normalized so the constant 1 spectrum is white.
+fn xyz_to_rgb(xyz: [f64; 3]) -> [f64; 3] {
+    let rgb = mat_mul(&XYZ_TO_SRGB, xyz);
+    let white = tables().white;
+    [rgb[0] / white[0], rgb[1] / white[1], rgb[2] / white[2]]
+}
+
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+/// CIE 1931 2° colour matching functions.
+///
+/// Multi-lobe fit from "Simple Analytic Approximations to the CIE XYZ
+/// Color Matching Functions" by Wyman et al.
+fn cie_xyz(lambda: f64) -> [f64; 3] {
+    fn g(x: f64, mu: f64, sigma_left: f64, sigma_right: f64) -> f64 {
+        let sigma = if x < mu { sigma_left } else { sigma_right };
+        let t = (x - mu) / sigma;
+        (-0.5 * t * t).exp()
+    }
+    let x = 1.056 * g(lambda, 599.8, 37.9, 31.0) + 0.362 * g(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * g(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * g(lambda, 568.8, 46.9, 40.5) + 0.286 * g(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * g(lambda, 437.0, 11.8, 36.0) + 0.681 * g(lambda, 459.0, 26.0, 13.8);
+    [x, y, z]
+}
+
+/// Red, green and blue spectra adding up to 1 at every wavelength.
+fn basis(lambda: f64) -> [f64; 3] {
+    let sigmoid = |x: f64| 1.0 / (1.0 + (-x).exp());
+    let width = 12.0;
+    let red = sigmoid((lambda - 590.0) / width);
+    let blue = 1.0 - sigmoid((lambda - 490.0) / width);
+    let green = 1.0 - red - blue;
+    [red, green, blue]
+}
+
+struct Tables {
+    /// RGB of the constant 1 spectrum before normalization.
+    white: [f64; 3],
+    rgb_to_basis: [[f64; 3]; 3],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        // integrate with 1nm steps
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let integrate = |spectrum: &dyn Fn(f64) -> f64| {
+            let mut xyz = [0.0; 3];
+            for step in 0..steps {
+                let lambda = LAMBDA_MIN + step as f64 + 0.5;
+                let cmf = cie_xyz(lambda);
+                let value = spectrum(lambda);
+                for (acc, c) in xyz.iter_mut().zip(cmf.iter()) {
+                    *acc += value * c;
+                }
+            }
+            mat_mul(&XYZ_TO_SRGB, xyz)
+        };
+
+        let white = integrate(&|_| 1.0);
+        let basis_rgb: [[f64; 3]; 3] =
+            std::array::from_fn(|channel| integrate(&|lambda| basis(lambda)[channel]));
+        let basis_to_rgb = std::array::from_fn(|row| {
+            std::array::from_fn(|channel| basis_rgb[channel][row] / white[row])
+        });
+        Tables {
+            white,
+            rgb_to_basis: invert(&basis_to_rgb),
+        }
+    })
+}
+
+fn mat_mul(m: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let adjugate = [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ];
+    let det = m[0][0] * adjugate[0][0] + m[0][1] * adjugate[1][0] + m[0][2] * adjugate[2][0];
+    adjugate.map(|row| row.map(|e| e / det))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close_enough(a: Color, b: Color) -> bool {
+        (a.r() - b.r()).abs() < 1e-3 && (a.g() - b.g()).abs() < 1e-3 && (a.b() - b.b()).abs() < 1e-3
+    }
+
+    #[test]
+    pub fn white_is_constant_one() {
+        for lambda in [400.0, 500.0, 600.0, 700.0] {
+            assert!((from_rgb(Color::rgb(1.0, 1.0, 1.0), lambda) - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    pub fn rgb_survives_round_trip() {
+        let color = Color::rgb(0.8, 0.3, 0.1);
+        // average many hero samples to integrate over the spectrum
+        let n = 10000;
+        let mut sum = Color::rgb(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let wavelengths = Wavelengths::sample((i as f64 + 0.5) / n as f64);
+            sum += to_rgb(sample_rgb(color, &wavelengths), &wavelengths);
+        }
+        assert!(close_enough(sum.scale(1.0 / n as f64), color));
+    }
+}
@@ -0,0 +1,205 @@
+//! Counters of the work done while rendering.
+//!
+//! Every thread counts for itself, without locks.  Whoever runs the
+//! work takes the counts of its thread with `take' and adds them up.
+
+use std::{cell::Cell, ops::AddAssign, time::Duration};
+
+/// How a path from the camera ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathEnd {
+    /// Left the scene.
+    Escaped,
+    /// A material didn't scatter it.
+    Absorbed,
+    /// Russian roulette ended it.
+    Roulette,
+    /// Reached the largest number of bounces allowed.
+    DepthLimit,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Rays traced through the scene, shadow rays too.
+    pub rays: u64,
+    /// Rays tested against a single shape.
+    pub intersection_tests: u64,
+    /// Paths started from the camera.
+    pub paths: u64,
+    /// Surfaces the paths from the camera hit, summed over the paths.
+    pub path_vertices: u64,
+    pub escaped: u64,
+    pub absorbed: u64,
+    pub roulette: u64,
+    pub depth_limited: u64,
+}
+
+const EMPTY: Stats = Stats {
+    rays: 0,
+    intersection_tests: 0,
+    paths: 0,
+    path_vertices: 0,
+    escaped: 0,
+    absorbed: 0,
+    roulette: 0,
+    depth_limited: 0,
+};
+
+thread_local! {
+    static COUNTS: Cell<Stats> = const { Cell::new(EMPTY) };
+}
+
+fn count<F: FnOnce(&mut Stats)>(update: F) {
+    COUNTS.with(|counts| {
+        let mut stats = counts.get();
+        update(&mut stats);
+        counts.set(stats);
+    })
+}
+
+pub fn ray_cast() {
+    count(|stats| stats.rays += 1)
+}
+
+pub fn intersection_test() {
+    count(|stats| stats.intersection_tests += 1)
+}
+
+/// A path from the camera ended with `end' after hitting `vertices'
+/// surfaces.
+pub fn path_ended(vertices: usize, end: PathEnd) {
+    count(|stats| {
+        stats.paths += 1;
+        stats.path_vertices += vertices as u64;
+        match end {
+            PathEnd::Escaped => stats.escaped += 1,
+            PathEnd::Absorbed => stats.absorbed += 1,
+            PathEnd::Roulette => stats.roulette += 1,
+            PathEnd::DepthLimit => stats.depth_limited += 1,
+        }
+    })
+}
+
+/// What the current thread counted since the last call.
+pub fn take() -> Stats {
+    COUNTS.with(|counts| counts.replace(EMPTY))
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.rays += rhs.rays;
+        self.intersection_tests += rhs.intersection_tests;
+        self.paths += rhs.paths;
+        self.path_vertices += rhs.path_vertices;
+        self.escaped += rhs.escaped;
+        self.absorbed += rhs.absorbed;
+        self.roulette += rhs.roulette;
+        self.depth_limited += rhs.depth_limited;
+    }
+}
+
+impl Stats {
+    /// Summary of a render that took `elapsed', with the time taken
+    /// by each of its frames.
+    pub fn report(&self, elapsed: Duration, frame_times: &[Duration]) -> String {
+        let seconds = elapsed.as_secs_f64().max(1e-9);
+        let paths = self.paths.max(1) as f64;
+        let share = |count: u64| 100.0 * count as f64 / paths;
+        let mut lines = vec![
+            format!(
+                "Rendered {} frames in {}",
+                frame_times.len(),
+                format_duration(elapsed)
+            ),
+            format!(
+                "  rays cast:          {} ({:.2} M rays/s)",
+                self.rays,
+                self.rays as f64 / seconds / 1e6
+            ),
+            format!("  intersection tests: {}", self.intersection_tests),
+            format!(
+                "  camera paths:       {}, {:.2} surfaces hit on average",
+                self.paths,
+                self.path_vertices as f64 / paths
+            ),
+            format!(
+                "  paths ended by:     escaping {:.1}%, absorption {:.1}%, \
+                 roulette {:.1}%, depth {:.1}%",
+                share(self.escaped),
+                share(self.absorbed),
+                share(self.roulette),
+                share(self.depth_limited)
+            ),
+        ];
+        if let (Some(min), Some(max)) = (frame_times.iter().min(), frame_times.iter().max()) {
+            let mean = frame_times.iter().sum::<Duration>() / frame_times.len() as u32;
+            lines.push(format!(
+                "  time per frame:     {} on average, {} to {}",
+                format_duration(mean),
+                format_duration(*min),
+                format_duration(*max)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Like `1h02m03s', or `4.5s' under a minute.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds < 60.0 {
+        return format!("{:.1}s", seconds);
+    }
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h{:02}m{:02}s", hours, minutes, seconds)
+    } else {
+        format!("{}m{:02}s", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn counts_are_taken_per_thread() {
+        take();
+        ray_cast();
+        intersection_test();
+        intersection_test();
+        path_ended(3, PathEnd::Escaped);
+        path_ended(1, PathEnd::Absorbed);
+        path_ended(5, PathEnd::Roulette);
+        let other = std::thread::spawn(|| {
+            ray_cast();
+            take()
+        })
+        .join()
+        .unwrap();
+
+        let stats = take();
+        assert_eq!(stats.rays, 1);
+        assert_eq!(stats.intersection_tests, 2);
+        assert_eq!((stats.paths, stats.path_vertices), (3, 9));
+        assert_eq!(
+            (
+                stats.escaped,
+                stats.absorbed,
+                stats.roulette,
+                stats.depth_limited
+            ),
+            (1, 1, 1, 0)
+        );
+        assert_eq!(other.rays, 1);
+        assert_eq!(take(), Stats::default());
+    }
+
+    #[test]
+    pub fn durations_are_readable() {
+        assert_eq!(format_duration(Duration::from_millis(4500)), "4.5s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m05s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+}
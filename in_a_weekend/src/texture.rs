@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use crate::{color::Color, p3::Point3, v3::Vec3};
+
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color;
+}
+
+pub struct SolidColor(Color);
+
+impl SolidColor {
+    pub fn new_arc(color: Color) -> Arc<Self> {
+        Arc::new(Self(color))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Point3) -> Color {
+        self.0
+    }
+}
+
+/// Checker pattern in space, with cubes of size `scale'.
+pub struct Checker {
+    odd: Arc<dyn Texture + Send + Sync>,
+    even: Arc<dyn Texture + Send + Sync>,
+    scale: f64,
+}
+
+impl Checker {
+    pub fn new_arc(
+        odd: Arc<dyn Texture + Send + Sync>,
+        even: Arc<dyn Texture + Send + Sync>,
+        scale: f64,
+    ) -> Arc<Self> {
+        Arc::new(Self { odd, even, scale })
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        let (x, y, z) = point.xyz();
+        let cell = |c: f64| (c / self.scale).floor() as i64;
+        if (cell(x) + cell(y) + cell(z)).rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+/// Fractal value noise between black and white, good for masks like
+/// rust or dirt.  Lower `scale' gives bigger blotches.
+pub struct Noise {
+    scale: f64,
+    octaves: usize,
+}
+
+impl Noise {
+    pub fn new_arc(scale: f64, octaves: usize) -> Arc<Self> {
+        Arc::new(Self { scale, octaves })
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _u: f64, _v: f64, point: Point3) -> Color {
+        let mut p = Vec3::from(point).scale(self.scale);
+        let mut amplitude = 0.5;
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        for _octave in 0..self.octaves {
+            sum += amplitude * value_noise(p);
+            total += amplitude;
+            amplitude *= 0.5;
+            p = p.scale(2.0);
+        }
+        let value = if total > 0.0 { sum / total } else { 0.0 };
+        Color::rgb(value, value, value)
+    }
+}
+
+/// Smoothly interpolated random values on the integer lattice.
+fn value_noise(p: Vec3) -> f64 {
+    let (x, y, z) = p.xyz();
+    let (ix, iy, iz) = (x.floor(), y.floor(), z.floor());
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (fx, fy, fz) = (smooth(x - ix), smooth(y - iy), smooth(z - iz));
+    let corner =
+        |dx: i64, dy: i64, dz: i64| lattice_value(ix as i64 + dx, iy as i64 + dy, iz as i64 + dz);
+    let lerp = |a: f64, b: f64, t: f64| a + t * (b - a);
+
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fx);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fx);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fx);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fx);
+    lerp(lerp(x00, x10, fy), lerp(x01, x11, fy), fz)
+}
+
+fn lattice_value(x: i64, y: i64, z: i64) -> f64 {
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Checker pattern in texture space with `cells' squares along each
+/// side.  Coordinates outside of `[0, 1)' wrap around.
+pub struct UvChecker {
+    odd: Arc<dyn Texture + Send + Sync>,
+    even: Arc<dyn Texture + Send + Sync>,
+    cells: f64,
+}
+
+impl UvChecker {
+    pub fn new_arc(
+        odd: Arc<dyn Texture + Send + Sync>,
+        even: Arc<dyn Texture + Send + Sync>,
+        cells: f64,
+    ) -> Arc<Self> {
+        Arc::new(Self { odd, even, cells })
+    }
+}
+
+impl Texture for UvChecker {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        let cell = |c: f64| (c.rem_euclid(1.0) * self.cells).floor() as i64;
+        if (cell(u) + cell(v)).rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn checker_colors() -> (Arc<SolidColor>, Arc<SolidColor>) {
+        (
+            SolidColor::new_arc(Color::rgb(1.0, 1.0, 1.0)),
+            SolidColor::new_arc(Color::rgb(0.0, 0.0, 0.0)),
+        )
+    }
+
+    #[test]
+    pub fn checker_alternates_between_cells() {
+        let (odd, even) = checker_colors();
+        let checker = Checker::new_arc(odd, even, 0.5);
+        let at = |x: f64, y: f64, z: f64| checker.value(0.0, 0.0, Point3::new(x, y, z)).r();
+        assert_eq!(at(0.25, 0.25, 0.25), 0.0);
+        assert_eq!(at(0.75, 0.25, 0.25), 1.0);
+        assert_eq!(at(0.75, 0.75, 0.25), 0.0);
+        assert_eq!(at(0.75, 0.75, 0.75), 1.0);
+        // across the origin too
+        assert_eq!(at(-0.25, 0.25, 0.25), 1.0);
+        assert_eq!(at(-0.75, 0.25, 0.25), 0.0);
+    }
+
+    #[test]
+    pub fn uv_checker_wraps() {
+        let (odd, even) = checker_colors();
+        let checker = UvChecker::new_arc(odd, even, 3.0);
+        let at = |u: f64, v: f64| checker.value(u, v, Point3::new(0.0, 0.0, 0.0)).r();
+        assert_eq!(at(0.1, 0.1), 0.0);
+        assert_eq!(at(0.5, 0.1), 1.0);
+        for (u, v) in [(0.1, 0.1), (0.5, 0.1), (0.5, 0.9)] {
+            assert_eq!(at(u + 1.0, v), at(u, v));
+            assert_eq!(at(u, v - 1.0), at(u, v));
+        }
+    }
+
+    #[test]
+    pub fn noise_is_gray_between_black_and_white() {
+        let noise = Noise::new_arc(3.0, 4);
+        let mut values = Vec::new();
+        for i in 0..1000 {
+            let point = Point3::new(i as f64 * 0.173, i as f64 * -0.071, i as f64 * 0.029);
+            let value = noise.value(0.0, 0.0, point);
+            assert!(value.r() == value.g() && value.g() == value.b());
+            assert!((0.0..=1.0).contains(&value.r()));
+            assert_eq!(noise.value(0.0, 0.0, point).r(), value.r());
+            values.push(value.r());
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!((mean - 0.5).abs() < 0.1, "{}", mean);
+    }
+}
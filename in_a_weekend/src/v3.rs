@@ -77,6 +77,7 @@ impl Vec3 {
         self.e1.abs() < epsilon && self.e2.abs() < epsilon && self.e3.abs() < epsilon
     }
 
+    #[allow(clippy::neg_multiply)]
     pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
         let e1 = a.y() * b.z() - a.z() * b.y();
         let minus_e2 = a.x() * b.z() - a.z() * b.x();
@@ -131,17 +132,9 @@ impl Sub for Vec3 {
 
 impl AddAssign for Vec3 {
     fn add_assign(&mut self, rhs: Self) {
-        self.e1 += fix_nan(rhs.e1);
-        self.e2 += fix_nan(rhs.e2);
-        self.e3 += fix_nan(rhs.e3);
-    }
-}
-
-fn fix_nan(v: f64) -> f64 {
-    if v.is_nan() {
-        0.0
-    } else {
-        v
+        self.e1 += rhs.e1;
+        self.e2 += rhs.e2;
+        self.e3 += rhs.e3;
     }
 }
 
@@ -168,11 +161,11 @@ mod test {
     }
 
     #[test]
-    pub fn accummulating_nan_doesnt_kill() {
+    pub fn accumulating_nan_is_not_hidden() {
         let mut v = Vec3::zero();
         let with_nan = Vec3::new(f64::NAN, 0.0, 0.0);
         v += with_nan;
-        assert_eq!(v.x(), 0.0);
+        assert!(v.x().is_nan());
     }
 
     #[test]
@@ -0,0 +1,299 @@
+//! ꟻLIP, the difference between two images as seen by someone
+//! flipping back and forth between them, after "FLIP: A Difference
+//! Evaluator for Alternating Images" by Andersson et al.  Only the
+//! version for images with channels in `[0, 1]'.
+//!
+//! Both images are blurred like the eye would at the viewing distance
+//! and compared in a perceptually uniform color space.  Edges and
+//! points that show up in only one of them make the difference
+//! stronger.
+
+use std::f64::consts::PI;
+
+use rt_iaw::{Color, Rect};
+
+/// A 0.7m wide 4K monitor seen from 0.7m, as in the paper.
+pub const PIXELS_PER_DEGREE: f64 = 0.7 * 3840.0 / 0.7 * PI / 180.0;
+
+/// Exponents of the color and the feature differences.
+const COLOR_EXPONENT: f64 = 0.7;
+const FEATURE_EXPONENT: f64 = 0.5;
+/// Color differences below this fraction of the largest one are
+/// mapped to errors below `ERROR_AT_COLOR_CUTOFF'.
+const COLOR_CUTOFF: f64 = 0.4;
+const ERROR_AT_COLOR_CUTOFF: f64 = 0.95;
+/// Width of the edge and point detectors in degrees.
+const FEATURE_WIDTH: f64 = 0.082;
+
+type Pixel = [f64; 3];
+
+/// Error between 0 and 1 of every pixel of `test' against
+/// `reference', both of `size' and encoded in sRGB.
+pub fn flip(size: &Rect, reference: &[Color], test: &[Color], pixels_per_degree: f64) -> Vec<f64> {
+    let reference: Vec<Pixel> = reference.iter().map(|&c| srgb_to_ycxcz(c)).collect();
+    let test: Vec<Pixel> = test.iter().map(|&c| srgb_to_ycxcz(c)).collect();
+
+    let color_differences = {
+        let filters = contrast_sensitivity_filters(pixels_per_degree);
+        let filtered = |image: &[Pixel]| -> Vec<Pixel> {
+            let channels: Vec<Vec<f64>> = (0..3)
+                .map(|channel| {
+                    let values: Vec<f64> = image.iter().map(|p| p[channel]).collect();
+                    filters[channel].apply(size, &values)
+                })
+                .collect();
+            (0..image.len())
+                .map(|index| {
+                    let ycxcz = [channels[0][index], channels[1][index], channels[2][index]];
+                    let linear = xyz_to_linear(ycxcz_to_xyz(ycxcz));
+                    hunt_lab(linear.map(|c| c.clamp(0.0, 1.0)))
+                })
+                .collect()
+        };
+        let (reference, test) = (filtered(&reference), filtered(&test));
+        let largest =
+            hyab(hunt_lab([0.0, 1.0, 0.0]), hunt_lab([0.0, 0.0, 1.0])).powf(COLOR_EXPONENT);
+        reference
+            .iter()
+            .zip(&test)
+            .map(|(&r, &t)| redistribute(hyab(r, t).powf(COLOR_EXPONENT), largest))
+            .collect::<Vec<f64>>()
+    };
+
+    let feature_differences = {
+        let detectors = [
+            feature_detector(pixels_per_degree, Feature::Edge),
+            feature_detector(pixels_per_degree, Feature::Point),
+        ];
+        // luminance back in [0, 1]
+        let luminance =
+            |image: &[Pixel]| -> Vec<f64> { image.iter().map(|p| (p[0] + 16.0) / 116.0).collect() };
+        let (reference, test) = (luminance(&reference), luminance(&test));
+        let strengths = |detector: &(Filter, Filter), image: &[f64]| -> Vec<f64> {
+            let (x, y) = (detector.0.apply(size, image), detector.1.apply(size, image));
+            x.iter().zip(&y).map(|(x, y)| x.hypot(*y)).collect()
+        };
+        let [edges, points] = detectors.map(|detector| {
+            let (r, t) = (
+                strengths(&detector, &reference),
+                strengths(&detector, &test),
+            );
+            r.iter()
+                .zip(&t)
+                .map(|(r, t)| (r - t).abs())
+                .collect::<Vec<f64>>()
+        });
+        edges
+            .iter()
+            .zip(&points)
+            .map(|(edge, point)| {
+                (edge.max(*point) / 2f64.sqrt())
+                    .powf(FEATURE_EXPONENT)
+                    .clamp(0.0, 1.0)
+            })
+            .collect::<Vec<f64>>()
+    };
+
+    color_differences
+        .iter()
+        .zip(&feature_differences)
+        .map(|(color, feature)| color.powf(1.0 - feature))
+        .collect()
+}
+
+/// Map color differences to errors, most of the range goes to the
+/// small differences.
+fn redistribute(difference: f64, largest: f64) -> f64 {
+    let cutoff = COLOR_CUTOFF * largest;
+    if difference < cutoff {
+        ERROR_AT_COLOR_CUTOFF / cutoff * difference
+    } else {
+        ERROR_AT_COLOR_CUTOFF
+            + (difference - cutoff) / (largest - cutoff) * (1.0 - ERROR_AT_COLOR_CUTOFF)
+    }
+}
+
+/// Square kernel of `2 radius + 1' pixels a side, applied with the
+/// pixels at the borders repeated.
+struct Filter {
+    radius: usize,
+    weights: Vec<f64>,
+}
+
+impl Filter {
+    fn new(radius: usize, weight: impl Fn(f64, f64) -> f64) -> Self {
+        let side = 2 * radius + 1;
+        let weights = (0..side * side)
+            .map(|i| {
+                let x = (i % side) as f64 - radius as f64;
+                let y = (i / side) as f64 - radius as f64;
+                weight(x, y)
+            })
+            .collect();
+        Self { radius, weights }
+    }
+
+    fn apply(&self, size: &Rect, values: &[f64]) -> Vec<f64> {
+        let side = 2 * self.radius + 1;
+        let clamped = |c: usize, offset: usize, len: usize| {
+            (c + offset).saturating_sub(self.radius).min(len - 1)
+        };
+        (0..values.len())
+            .map(|index| {
+                let (x, y) = (index % size.width, index / size.width);
+                let mut sum = 0.0;
+                for (i, weight) in self.weights.iter().enumerate() {
+                    let sx = clamped(x, i % side, size.width);
+                    let sy = clamped(y, i / side, size.height);
+                    sum += weight * values[sy * size.width + sx];
+                }
+                sum
+            })
+            .collect()
+    }
+}
+
+/// Blur of the eye for the achromatic, red-green and blue-yellow
+/// channels, each a sum of two Gaussians.
+fn contrast_sensitivity_filters(pixels_per_degree: f64) -> [Filter; 3] {
+    let parameters = [
+        (1.0, 0.0047, 0.0, 1e-5),
+        (1.0, 0.0053, 0.0, 1e-5),
+        (34.1, 0.04, 13.5, 0.025),
+    ];
+    // wide enough for the widest Gaussian
+    let widest: f64 = 0.04;
+    let radius = (3.0 * (widest / (2.0 * PI * PI)).sqrt() * pixels_per_degree).ceil() as usize;
+    parameters.map(|(a1, b1, a2, b2)| {
+        let gaussian =
+            |a: f64, b: f64, distance2: f64| a * (PI / b).sqrt() * (-PI * PI * distance2 / b).exp();
+        let mut filter = Filter::new(radius, |x, y| {
+            let distance2 = (x * x + y * y) / (pixels_per_degree * pixels_per_degree);
+            gaussian(a1, b1, distance2) + gaussian(a2, b2, distance2)
+        });
+        let sum: f64 = filter.weights.iter().sum();
+        filter.weights.iter_mut().for_each(|w| *w /= sum);
+        filter
+    })
+}
+
+enum Feature {
+    Edge,
+    Point,
+}
+
+/// First or second derivatives of a Gaussian along `x' and `y', with
+/// the positive and the negative weights both summing to one.
+fn feature_detector(pixels_per_degree: f64, feature: Feature) -> (Filter, Filter) {
+    let deviation = 0.5 * FEATURE_WIDTH * pixels_per_degree;
+    let radius = (3.0 * deviation).ceil() as usize;
+    let derivative = |x: f64, y: f64| {
+        let gaussian = (-(x * x + y * y) / (2.0 * deviation * deviation)).exp();
+        match feature {
+            Feature::Edge => -x * gaussian,
+            Feature::Point => (x * x / (deviation * deviation) - 1.0) * gaussian,
+        }
+    };
+    let along_x = Filter::new(radius, derivative);
+    let positive: f64 = along_x.weights.iter().filter(|w| **w > 0.0).sum();
+    let negative: f64 = -along_x.weights.iter().filter(|w| **w < 0.0).sum::<f64>();
+    let normalized = |x: f64, y: f64| {
+        let w = derivative(x, y);
+        if w < 0.0 {
+            w / negative
+        } else {
+            w / positive
+        }
+    };
+    (
+        Filter::new(radius, normalized),
+        Filter::new(radius, |x, y| normalized(y, x)),
+    )
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c > 0.04045 {
+        ((c + 0.055) / 1.055).powf(2.4)
+    } else {
+        c / 12.92
+    }
+}
+
+fn linear_to_xyz([r, g, b]: Pixel) -> Pixel {
+    [
+        (10135552.0 * r + 8788810.0 * g + 4435075.0 * b) / 24577794.0,
+        (2613072.0 * r + 8788810.0 * g + 887015.0 * b) / 12288897.0,
+        (1425312.0 * r + 8788810.0 * g + 70074185.0 * b) / 73733382.0,
+    ]
+}
+
+fn xyz_to_linear([x, y, z]: Pixel) -> Pixel {
+    [
+        3.241003275 * x - 1.537398934 * y - 0.498615861 * z,
+        -0.969224334 * x + 1.875930071 * y + 0.041554224 * z,
+        0.055639423 * x - 0.204011202 * y + 1.057148933 * z,
+    ]
+}
+
+/// The white everything is relative to.
+fn white() -> Pixel {
+    linear_to_xyz([1.0, 1.0, 1.0])
+}
+
+fn srgb_to_ycxcz(c: Color) -> Pixel {
+    let linear = [c.r(), c.g(), c.b()].map(srgb_to_linear);
+    let [x, y, z] = linear_to_xyz(linear);
+    let [xw, yw, zw] = white();
+    let (x, y, z) = (x / xw, y / yw, z / zw);
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+fn ycxcz_to_xyz([y, cx, cz]: Pixel) -> Pixel {
+    let y = (y + 16.0) / 116.0;
+    let [xw, yw, zw] = white();
+    [(y + cx / 500.0) * xw, y * yw, (y - cz / 200.0) * zw]
+}
+
+/// L*a*b* of a linear color, with the chroma scaled down in the dark
+/// like the Hunt effect.
+fn hunt_lab(linear: Pixel) -> Pixel {
+    let [x, y, z] = linear_to_xyz(linear);
+    let [xw, yw, zw] = white();
+    let delta: f64 = 6.0 / 29.0;
+    let f = |t: f64| {
+        if t > delta.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x / xw), f(y / yw), f(z / zw));
+    let l = 116.0 * fy - 16.0;
+    let (a, b) = (500.0 * (fx - fy), 200.0 * (fy - fz));
+    [l, 0.01 * l * a, 0.01 * l * b]
+}
+
+/// Distance of lightness plus the distance of the colors.
+fn hyab(a: Pixel, b: Pixel) -> f64 {
+    (a[0] - b[0]).abs() + (a[1] - b[1]).hypot(a[2] - b[2])
+}
+
+#[test]
+pub fn errors_grow_with_the_difference() {
+    let size = Rect {
+        width: 16,
+        height: 16,
+    };
+    let gray = |value: f64| vec![Color::rgb(value, value, value); 16 * 16];
+    let mean_error = |reference: &[Color], test: &[Color]| {
+        flip(&size, reference, test, PIXELS_PER_DEGREE)
+            .iter()
+            .sum::<f64>()
+            / 256.0
+    };
+    assert_eq!(mean_error(&gray(0.5), &gray(0.5)), 0.0);
+    let small = mean_error(&gray(0.5), &gray(0.55));
+    let large = mean_error(&gray(0.5), &gray(0.8));
+    assert!(0.0 < small && small < large, "{} {}", small, large);
+    assert!(mean_error(&gray(0.0), &gray(1.0)) > 0.9);
+}
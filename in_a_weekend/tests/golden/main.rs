@@ -0,0 +1,262 @@
+//! Golden image tests.  Small scenes are rendered with a fixed seed
+//! and compared with the reference images in `golden/'.
+//!
+//! A failed comparison leaves the render, an image of the differences
+//! and one of the ꟻLIP errors in `golden/' under the temporary
+//! directory of the tests in `target/'.  After a change that is meant
+//! to alter the pictures, run the tests with `UPDATE_GOLDEN=1' to
+//! write the references again, and look at them before committing.
+
+mod flip;
+
+use std::path::{Path, PathBuf};
+
+use rt_iaw::{
+    camera::Camera,
+    color::Color,
+    film::{Film, SampleCount},
+    filter::Filter,
+    hittable::{
+        material::{
+            dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian,
+            metal::Metal,
+        },
+        sphere::Sphere,
+    },
+    integrator::Integrator,
+    p3::Point3,
+    ppm::{self, Ppm},
+    render::{RenderSettings, Renderer},
+    sampler::SamplerKind,
+    scene::Scene,
+    v3::Vec3,
+    Rect,
+};
+
+const SIZE: usize = 40;
+const SAMPLES: usize = 32;
+const SEED: u64 = 7;
+
+/// Largest root mean square difference of the channels allowed.  The
+/// same code renders the same image, this only leaves room for other
+/// compilers and optimizations.  Another seed is far above it.
+const MAX_RMSE: f64 = 0.006;
+/// Largest mean ꟻLIP error allowed, catches changes that are easy to
+/// see even where they don't add up to much of a difference.
+const MAX_FLIP: f64 = 0.005;
+
+/// Diffuse, glass and metal spheres on a diffuse ground, lit by the
+/// sky and a small light above the glass.
+fn spheres() -> (Scene, Camera) {
+    let mut scene = Scene::new();
+    let ground = Lambertian::new_arc(Color::rgb(0.5, 0.5, 0.5));
+    scene.add(Sphere::new_arc(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    ));
+    scene.add(Sphere::new_arc(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Dielectric::new_arc(1.5),
+    ));
+    scene.add(Sphere::new_arc(
+        Point3::new(-1.1, 0.0, -1.0),
+        0.5,
+        Metal::new_arc(Color::rgb(0.8, 0.6, 0.2), 0.3),
+    ));
+    scene.add(Sphere::new_arc(
+        Point3::new(1.1, 0.0, -1.0),
+        0.5,
+        Lambertian::new_arc(Color::rgb(0.1, 0.2, 0.5)),
+    ));
+    scene.add_light(Sphere::new_arc(
+        Point3::new(0.0, 1.2, -1.0),
+        0.2,
+        DiffuseLight::new_arc(Color::rgb(4.0, 4.0, 4.0)),
+    ));
+    scene.set_caustic_bounds(Point3::new(0.0, 0.0, -1.0), 0.6);
+
+    let camera = Camera::new(
+        Point3::new(0.0, 0.8, 1.5),
+        Point3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    (scene, camera)
+}
+
+fn settings(integrator: Integrator) -> RenderSettings {
+    RenderSettings {
+        size: Rect {
+            width: SIZE,
+            height: SIZE,
+        },
+        integrator,
+        samples: SampleCount::Fixed(SAMPLES),
+        filter: Filter::default(),
+        sampler: SamplerKind::Sobol,
+        max_depth: 16,
+        photons: 20_000,
+        photon_radius: 0.05,
+        aovs: Vec::new(),
+    }
+}
+
+fn read(path: &Path) -> (Rect, Vec<Color>) {
+    let mut file = std::fs::File::open(path)
+        .unwrap_or_else(|error| panic!("Couldn't open {}: {}", path.display(), error));
+    ppm::read(&mut file)
+        .unwrap_or_else(|error| panic!("Couldn't read {}: {}", path.display(), error))
+}
+
+fn difference(a: Color, b: Color) -> [f64; 3] {
+    [
+        (a.r() - b.r()).abs(),
+        (a.g() - b.g()).abs(),
+        (a.b() - b.b()).abs(),
+    ]
+}
+
+/// Root mean square difference of the channels of two images.
+fn rmse(actual: &[Color], expected: &[Color]) -> f64 {
+    let sum: f64 = actual
+        .iter()
+        .zip(expected)
+        .flat_map(|(&a, &b)| difference(a, b))
+        .map(|d| d * d)
+        .sum();
+    (sum / (3 * actual.len()) as f64).sqrt()
+}
+
+/// Differences of the channels made four times brighter.
+fn write_difference(path: &Path, size: &Rect, actual: &[Color], expected: &[Color]) {
+    let mut file = std::fs::File::create(path).unwrap();
+    Ppm::new(size.clone())
+        .write(&mut file, |pixel: Rect| {
+            let index = pixel.height * size.width + pixel.width;
+            let [r, g, b] = difference(actual[index], expected[index]);
+            Color::rgb(r, g, b).scale(4.0).sampled(1)
+        })
+        .unwrap();
+}
+
+/// ꟻLIP errors from black for none to white for the largest.
+fn write_flip(path: &Path, size: &Rect, errors: &[f64]) {
+    let mut file = std::fs::File::create(path).unwrap();
+    Ppm::new(size.clone())
+        .write(&mut file, |pixel: Rect| {
+            let error = errors[pixel.height * size.width + pixel.width];
+            Color::rgb(error, error, error).sampled(1)
+        })
+        .unwrap();
+}
+
+/// Render the spheres with `integrator' and compare them with the
+/// reference image `name'.
+fn check(name: &str, integrator: Integrator) {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let reference = root.join("golden").join(format!("{}.ppm", name));
+    let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&out).unwrap();
+    let rendered = out.join(format!("{}.ppm", name));
+
+    let (mut scene, camera) = spheres();
+    let film = Renderer::new(settings(integrator))
+        .with_seed(SEED)
+        .render(&mut scene, &camera);
+    film.write(out.join(name).to_str().unwrap()).unwrap();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        std::fs::copy(&rendered, &reference).unwrap();
+        return;
+    }
+    assert!(
+        reference.exists(),
+        "No reference for {}, run with UPDATE_GOLDEN=1 to make one",
+        name
+    );
+
+    let (size, actual) = read(&rendered);
+    let (expected_size, expected) = read(&reference);
+    assert_eq!(
+        (size.width, size.height),
+        (expected_size.width, expected_size.height),
+        "{}: size changed",
+        name
+    );
+    let rmse = rmse(&actual, &expected);
+    let flip_errors = flip::flip(&size, &expected, &actual, flip::PIXELS_PER_DEGREE);
+    let flip = flip_errors.iter().sum::<f64>() / flip_errors.len() as f64;
+    if rmse > MAX_RMSE || flip > MAX_FLIP {
+        let diff = out.join(format!("{}_diff.ppm", name));
+        write_difference(&diff, &size, &actual, &expected);
+        let flip_map = out.join(format!("{}_flip.ppm", name));
+        write_flip(&flip_map, &size, &flip_errors);
+        panic!(
+            "{} differs from the reference, RMSE {:.4}, mean FLIP {:.4}, see {}, {} and {}",
+            name,
+            rmse,
+            flip,
+            rendered.display(),
+            diff.display(),
+            flip_map.display()
+        );
+    }
+}
+
+#[test]
+pub fn path() {
+    check("path", Integrator::Path);
+}
+
+#[test]
+pub fn spectral() {
+    check("spectral", Integrator::Spectral);
+}
+
+#[test]
+pub fn bidirectional() {
+    check("bidirectional", Integrator::Bidirectional);
+}
+
+#[test]
+pub fn photon() {
+    check("photon", Integrator::Photon);
+}
+
+#[test]
+pub fn resumed_render_is_the_same() {
+    let integrator = Integrator::Path;
+    let (mut scene, camera) = spheres();
+    let settings = settings(integrator);
+    let key = settings.key(SEED);
+    let checkpoint = std::env::temp_dir().join(format!("rt_iaw_golden_{}", std::process::id()));
+    let checkpoint = checkpoint.to_str().unwrap();
+
+    // saved after the first pass
+    let whole = Renderer::new(settings.clone())
+        .with_seed(SEED)
+        .on_pass(|film, samples| {
+            if samples < SAMPLES && !Path::new(checkpoint).exists() {
+                film.save_checkpoint(checkpoint, &key, samples).unwrap();
+            }
+        })
+        .render(&mut scene, &camera);
+    let saved = Film::load_checkpoint(checkpoint, &key, settings.size.clone(), &[]);
+    std::fs::remove_file(checkpoint).unwrap();
+    let (film, samples) = saved.unwrap().expect("Checkpoint with the same key");
+    assert!(samples < SAMPLES);
+    let resumed = Renderer::new(settings)
+        .with_seed(SEED)
+        .resuming(film, samples)
+        .render(&mut scene, &camera);
+
+    let channels = |c: &Color| (c.r(), c.g(), c.b());
+    assert!(whole
+        .beauty()
+        .iter()
+        .map(channels)
+        .eq(resumed.beauty().iter().map(channels)));
+    assert_eq!(whole.samples(), resumed.samples());
+}